@@ -0,0 +1,261 @@
+use std::{
+    cell::Cell,
+    fs::File,
+    io::{self, Read},
+    path,
+    rc::Rc,
+};
+
+use flate2::read::GzDecoder;
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    Corrupt(String),
+    RatioExceeded(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    fn from_name(name: &str) -> Option<ArchiveKind> {
+        let name = name.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct ArchiveLimits {
+    /// how many levels of archives are opened; the archive on disk is level 1
+    pub max_depth: usize,
+    /// largest allowed ratio of decompressed to compressed size, for each member and for
+    /// all members of the archive on disk together, nested ones included
+    pub max_ratio: u64,
+}
+
+pub fn is_archive(path: &path::Path) -> bool {
+    match path.file_name() {
+        Some(name) => ArchiveKind::from_name(&name.to_string_lossy()).is_some(),
+        None => false,
+    }
+}
+
+/// Calls `visit` with the member path chain and the contents of every regular-file member.
+/// Members that are archives themselves are opened recursively up to `limits.max_depth`.
+pub fn for_each_member(
+    path: &path::Path,
+    limits: &ArchiveLimits,
+    visit: &mut dyn FnMut(&[String], &[u8]),
+) -> Result<(), ArchiveError> {
+    let name = path.to_string_lossy().to_string();
+    let kind = match path.file_name() {
+        Some(file_name) => ArchiveKind::from_name(&file_name.to_string_lossy()),
+        None => None,
+    };
+    let kind = match kind {
+        Some(kind) => kind,
+        None => return Err(ArchiveError::Corrupt(name)),
+    };
+    let mut contents = Vec::new();
+    let mut f = match File::open(path) {
+        Ok(f) => f,
+        Err(err) => return Err(ArchiveError::Io(err)),
+    };
+    if let Err(err) = f.read_to_end(&mut contents) {
+        return Err(ArchiveError::Io(err));
+    }
+    // every member is held in memory, so their total size is bounded as well
+    let mut budget = Budget {
+        limits,
+        remaining: (contents.len() as u64)
+            .max(1)
+            .saturating_mul(limits.max_ratio),
+    };
+    let mut chain = Vec::new();
+    walk(kind, &name, &contents, 1, &mut budget, &mut chain, visit)
+}
+
+struct Budget<'a> {
+    limits: &'a ArchiveLimits,
+    // decompressed bytes left for the members of the archive on disk
+    remaining: u64,
+}
+
+fn walk(
+    kind: ArchiveKind,
+    name: &str,
+    contents: &[u8],
+    depth: usize,
+    budget: &mut Budget,
+    chain: &mut Vec<String>,
+    visit: &mut dyn FnMut(&[String], &[u8]),
+) -> Result<(), ArchiveError> {
+    let members = match kind {
+        ArchiveKind::Tar => read_tar(name, contents, None, budget)?,
+        ArchiveKind::TarGz => {
+            let decoder = GzDecoder::new(contents);
+            read_tar(name, decoder, Some(contents.len() as u64), budget)?
+        }
+        ArchiveKind::Zip => read_zip(name, contents, budget)?,
+    };
+    for (member_path, member_contents) in members {
+        chain.push(member_path.clone());
+        match ArchiveKind::from_name(&member_path) {
+            Some(member_kind) if depth < budget.limits.max_depth => {
+                let member_name = format!("{}!{}", name, member_path);
+                walk(
+                    member_kind,
+                    &member_name,
+                    &member_contents,
+                    depth + 1,
+                    budget,
+                    chain,
+                    visit,
+                )?;
+            }
+            _ => visit(chain, &member_contents),
+        }
+        chain.pop();
+    }
+    Ok(())
+}
+
+/// Reads every regular file of a tar stream.
+/// `compressed_len` is the size of the compressed stream when `reader` decompresses on the fly.
+fn read_tar<R: Read>(
+    name: &str,
+    reader: R,
+    compressed_len: Option<u64>,
+    total: &mut Budget,
+) -> Result<Vec<(String, Vec<u8>)>, ArchiveError> {
+    let budget = compressed_len.map(|len| len.max(1).saturating_mul(total.limits.max_ratio));
+    let exceeded = Rc::new(Cell::new(false));
+    let counter = CountingReader {
+        inner: reader,
+        count: 0,
+        budget,
+        exceeded: exceeded.clone(),
+    };
+    let tar_error = |err: io::Error| {
+        if exceeded.get() {
+            return ArchiveError::RatioExceeded(name.to_string());
+        }
+        ArchiveError::Corrupt(format!("{}: {}", name, err))
+    };
+    let mut archive = tar::Archive::new(counter);
+    let entries = match archive.entries() {
+        Ok(entries) => entries,
+        Err(err) => return Err(tar_error(err)),
+    };
+    let mut members = Vec::new();
+    for entry in entries {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => return Err(tar_error(err)),
+        };
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let member_path = match entry.path() {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(err) => return Err(tar_error(err)),
+        };
+        let mut contents = Vec::new();
+        if let Err(err) = (&mut entry)
+            .take(total.remaining.saturating_add(1))
+            .read_to_end(&mut contents)
+        {
+            return Err(tar_error(err));
+        }
+        total.charge(&contents, name)?;
+        members.push((member_path, contents));
+    }
+    Ok(members)
+}
+
+fn read_zip(
+    name: &str,
+    contents: &[u8],
+    total: &mut Budget,
+) -> Result<Vec<(String, Vec<u8>)>, ArchiveError> {
+    let mut archive = match zip::ZipArchive::new(io::Cursor::new(contents)) {
+        Ok(archive) => archive,
+        Err(err) => return Err(ArchiveError::Corrupt(format!("{}: {}", name, err))),
+    };
+    let mut members = Vec::new();
+    for index in 0..archive.len() {
+        let entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(err) => return Err(ArchiveError::Corrupt(format!("{}: {}", name, err))),
+        };
+        if !entry.is_file() {
+            continue;
+        }
+        let member_path = entry.name().to_string();
+        // the declared size can lie, so the guard is enforced again while reading
+        let budget = entry.compressed_size().max(1).saturating_mul(total.limits.max_ratio);
+        if entry.size() > budget {
+            return Err(ArchiveError::RatioExceeded(format!("{}!{}", name, member_path)));
+        }
+        let mut member_contents = Vec::new();
+        let limit = budget.min(total.remaining).saturating_add(1);
+        if let Err(err) = entry.take(limit).read_to_end(&mut member_contents) {
+            return Err(ArchiveError::Corrupt(format!(
+                "{}!{}: {}",
+                name, member_path, err
+            )));
+        }
+        if member_contents.len() as u64 > budget {
+            return Err(ArchiveError::RatioExceeded(format!("{}!{}", name, member_path)));
+        }
+        total.charge(&member_contents, name)?;
+        members.push((member_path, member_contents));
+    }
+    Ok(members)
+}
+
+impl Budget<'_> {
+    /// Takes the size of `contents`, a member of the archive `name`, from the budget.
+    fn charge(&mut self, contents: &[u8], name: &str) -> Result<(), ArchiveError> {
+        match self.remaining.checked_sub(contents.len() as u64) {
+            Some(left) => {
+                self.remaining = left;
+                Ok(())
+            }
+            None => Err(ArchiveError::RatioExceeded(name.to_string())),
+        }
+    }
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+    budget: Option<u64>,
+    exceeded: Rc<Cell<bool>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        if let Some(budget) = self.budget {
+            if self.count > budget {
+                self.exceeded.set(true);
+                return Err(io::Error::other("decompressed size exceeds the allowed ratio"));
+            }
+        }
+        Ok(n)
+    }
+}
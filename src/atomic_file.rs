@@ -0,0 +1,51 @@
+// files replaced in one rename, so readers and interrupted runs never see half of one
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::Path,
+    process,
+};
+
+/// Writes `contents` to a temporary file next to `path`, flushes it to disk and renames
+/// it over `path`. On an error the temporary file is removed and `path` is left as it was.
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    write_with(path, contents, &mut |_| Ok(()))
+}
+
+/// Like `write`, and calls `before_rename` with the path of the temporary file once it
+/// is on disk, while `path` still has its old contents.
+pub fn write_with(
+    path: &Path,
+    contents: &[u8],
+    before_rename: &mut dyn FnMut(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let file_name = path.file_name().expect("a written path names a file");
+    // hidden, so that directory walks skip it
+    let mut tmp_name = OsString::new();
+    if !file_name.to_string_lossy().starts_with('.') {
+        tmp_name.push(".");
+    }
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".minigrep-{}.tmp", process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+    let result = write_and_rename(path, &tmp_path, contents, before_rename);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn write_and_rename(
+    path: &Path,
+    tmp_path: &Path,
+    contents: &[u8],
+    before_rename: &mut dyn FnMut(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut f = fs::File::create(tmp_path)?;
+    f.write_all(contents)?;
+    f.sync_all()?;
+    before_rename(tmp_path)?;
+    fs::rename(tmp_path, path)
+}
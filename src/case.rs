@@ -0,0 +1,123 @@
+// case-shape detection for `--preserve-case`
+
+#[derive(Debug, PartialEq)]
+enum Shape {
+    Lower,          // foo
+    Upper,          // FOO
+    Pascal,         // Foo, FooBar
+    Camel,          // fooBar
+    Snake,          // foo_bar
+    ScreamingSnake, // FOO_BAR
+    Kebab,          // foo-bar
+    ScreamingKebab, // FOO-BAR
+    Mixed,
+}
+
+fn detect_shape(text: &str) -> Shape {
+    let has_lower = text.chars().any(|c| c.is_lowercase());
+    let has_upper = text.chars().any(|c| c.is_uppercase());
+    let separator = if text.contains('_') {
+        Some('_')
+    } else if text.contains('-') {
+        Some('-')
+    } else {
+        None
+    };
+    match (separator, has_lower, has_upper) {
+        (Some('_'), _, false) => Shape::Snake,
+        (Some('_'), false, true) => Shape::ScreamingSnake,
+        (Some(_), _, false) => Shape::Kebab,
+        (Some(_), false, true) => Shape::ScreamingKebab,
+        (Some(_), true, true) => Shape::Mixed,
+        (None, _, false) => Shape::Lower,
+        (None, false, true) => Shape::Upper,
+        (None, true, true) => {
+            if text.starts_with(|c: char| c.is_uppercase()) {
+                Shape::Pascal
+            } else {
+                Shape::Camel
+            }
+        }
+    }
+}
+
+/// Splits an identifier into lowercase words at `_`, `-`, spaces and case changes.
+fn split_words(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if c == '_' || c == '-' || c == ' ' {
+            if !word.is_empty() {
+                words.push(word.clone());
+                word.clear();
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let prev = chars[index - 1];
+            let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            // `fooBar` splits before `B`; `HTTPServer` splits before `S`
+            if prev.is_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_uppercase() && next_is_lower)
+            {
+                words.push(word.clone());
+                word.clear();
+            }
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Rewrites `replacement` in the case shape of `matched`.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    let words = split_words(replacement);
+    match detect_shape(matched) {
+        Shape::Lower => replacement.to_lowercase(),
+        Shape::Upper => replacement.to_uppercase(),
+        Shape::Pascal => words.iter().map(|word| capitalize(word)).collect(),
+        Shape::Camel => words
+            .iter()
+            .enumerate()
+            .map(|(index, word)| {
+                if index == 0 {
+                    word.clone()
+                } else {
+                    capitalize(word)
+                }
+            })
+            .collect(),
+        Shape::Snake => words.join("_"),
+        Shape::ScreamingSnake => words.join("_").to_uppercase(),
+        Shape::Kebab => words.join("-"),
+        Shape::ScreamingKebab => words.join("-").to_uppercase(),
+        Shape::Mixed => replacement.to_string(),
+    }
+}
+
+/// Turns an identifier query such as `foo_bar` into a pattern that also matches
+/// `fooBar`, `FooBar`, `FOO_BAR` and `foo-bar`. Other queries are returned unchanged.
+pub fn identifier_pattern(query: &str) -> Option<String> {
+    let is_identifier = !query.is_empty()
+        && query
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    if !is_identifier {
+        return None;
+    }
+    let words = split_words(query);
+    Some(format!("(?i:{})", words.join("[_-]?")))
+}
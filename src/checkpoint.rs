@@ -0,0 +1,106 @@
+// per-file resume positions for `--state-file`, so repeated runs only search new lines
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use super::atomic_file;
+
+// how many bytes before the offset the fingerprint covers
+const FINGERPRINT_LEN: u64 = 4096;
+
+pub struct Checkpoint {
+    // inode of the file the offsets belong to
+    pub file_id: u64,
+    // offset of the first line not searched yet
+    pub byte_offset: u64,
+    // number of that line
+    pub line_number: i32,
+    // hash of the bytes just before `byte_offset`, which tells a file that was truncated
+    // and grew back past the offset from one that was only appended to
+    pub fingerprint: u64,
+}
+
+/// Hashes the bytes of `f` just before `byte_offset`, with 64-bit FNV-1a so that the
+/// value stays the same across builds.
+pub fn fingerprint<F: Read + Seek>(f: &mut F, byte_offset: u64) -> io::Result<u64> {
+    let start = byte_offset.saturating_sub(FINGERPRINT_LEN);
+    f.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    f.take(byte_offset - start).read_to_end(&mut bytes)?;
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    Ok(hash)
+}
+
+/// The key a file is stored under, so that `log` and `./log` share a checkpoint.
+pub fn key(filename: &Path) -> PathBuf {
+    fs::canonicalize(filename).unwrap_or_else(|_| filename.to_path_buf())
+}
+
+/// Reads a state file written by `save`; a missing file has no checkpoints.
+/// Each line is `inode offset line_number fingerprint path`.
+pub fn load(path: &Path) -> Result<BTreeMap<PathBuf, Checkpoint>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(format!("{}: {}", path.to_string_lossy(), err)),
+    };
+    let mut checkpoints = BTreeMap::new();
+    for (index, line) in contents.lines().enumerate() {
+        let invalid = || format!("{}:{}: invalid entry", path.to_string_lossy(), index + 1);
+        let mut fields = line.splitn(5, ' ');
+        let mut number = || fields.next().map(|field| field.parse::<u64>());
+        let (file_id, byte_offset, line_number, fingerprint) =
+            match (number(), number(), number(), number()) {
+                (
+                    Some(Ok(file_id)),
+                    Some(Ok(byte_offset)),
+                    Some(Ok(line_number)),
+                    Some(Ok(fingerprint)),
+                ) => (file_id, byte_offset, line_number, fingerprint),
+                _ => return Err(invalid()),
+            };
+        let filename = match fields.next() {
+            Some(filename) if !filename.is_empty() => PathBuf::from(filename),
+            _ => return Err(invalid()),
+        };
+        let line_number = match i32::try_from(line_number) {
+            Ok(x) => x,
+            Err(_) => return Err(invalid()),
+        };
+        let checkpoint = Checkpoint {
+            file_id,
+            byte_offset,
+            line_number,
+            fingerprint,
+        };
+        checkpoints.insert(filename, checkpoint);
+    }
+    Ok(checkpoints)
+}
+
+/// Replaces the state file in one rename, so an interrupted run keeps the old state.
+pub fn save(path: &Path, checkpoints: &BTreeMap<PathBuf, Checkpoint>) -> Result<(), String> {
+    let mut contents = String::new();
+    for (filename, checkpoint) in checkpoints {
+        contents.push_str(&format!(
+            "{} {} {} {} {}\n",
+            checkpoint.file_id,
+            checkpoint.byte_offset,
+            checkpoint.line_number,
+            checkpoint.fingerprint,
+            filename.to_string_lossy()
+        ));
+    }
+    if let Err(err) = atomic_file::write(path, contents.as_bytes()) {
+        return Err(format!("{}: {}", path.to_string_lossy(), err));
+    }
+    Ok(())
+}
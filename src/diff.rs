@@ -0,0 +1,91 @@
+// unified diffs for line-by-line edits, where every old line maps to one new chunk of text
+
+const CONTEXT_LINES: usize = 3;
+
+pub struct LineEdit {
+    /// the old line, without its line ending
+    pub old: String,
+    /// the replacement for the line, without its line ending; may span several lines
+    pub new: String,
+    /// false for the last line of a file that does not end with a newline
+    pub has_newline: bool,
+}
+
+impl LineEdit {
+    fn is_changed(&self) -> bool {
+        self.old != self.new
+    }
+    fn new_lines(&self) -> Vec<&str> {
+        self.new.split('\n').collect()
+    }
+}
+
+pub fn unified_diff(path: &str, edits: &[LineEdit]) -> String {
+    let changed: Vec<usize> = (0..edits.len()).filter(|&i| edits[i].is_changed()).collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+    let mut diff = format!("--- a/{}\n+++ b/{}\n", path, path);
+    // group changes whose context overlaps into one hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        match hunks.last_mut() {
+            Some((_, last)) if i <= *last + 2 * CONTEXT_LINES + 1 => *last = i,
+            _ => hunks.push((i, i)),
+        }
+    }
+    // new line numbers drift when replacements add lines
+    let mut drift: isize = 0;
+    let mut counted = 0;
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT_LINES);
+        let end = (last + CONTEXT_LINES + 1).min(edits.len());
+        while counted < start {
+            drift += edits[counted].new_lines().len() as isize - 1;
+            counted += 1;
+        }
+        let mut body = String::new();
+        let mut new_len = 0;
+        for edit in &edits[start..end] {
+            if edit.is_changed() {
+                push_line(&mut body, '-', &edit.old, edit.has_newline);
+                let new_lines = edit.new_lines();
+                for (index, line) in new_lines.iter().enumerate() {
+                    let has_newline = edit.has_newline || index + 1 < new_lines.len();
+                    push_line(&mut body, '+', line, has_newline);
+                }
+                new_len += new_lines.len();
+            } else {
+                push_line(&mut body, ' ', &edit.old, edit.has_newline);
+                new_len += 1;
+            }
+        }
+        let new_start = (start as isize + drift) as usize;
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(start, end - start),
+            range(new_start, new_len)
+        ));
+        diff.push_str(&body);
+    }
+    diff
+}
+
+fn range(start: usize, len: usize) -> String {
+    // an empty range names the line before it
+    let first = if len == 0 { start } else { start + 1 };
+    if len == 1 {
+        first.to_string()
+    } else {
+        format!("{},{}", first, len)
+    }
+}
+
+fn push_line(body: &mut String, marker: char, line: &str, has_newline: bool) {
+    body.push(marker);
+    body.push_str(line);
+    body.push('\n');
+    if !has_newline {
+        body.push_str("\\ No newline at end of file\n");
+    }
+}
@@ -0,0 +1,141 @@
+// --include, --exclude, --exclude-dir, --iglob, -t and -T rules for the files found by globs
+// and walks
+
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobMatcher};
+
+use super::file_type::{TypeMatcher, Types};
+use super::grep_config::{FileRule, FileRuleKind, GrepConfig, GrepOption};
+
+pub struct FileFilter {
+    rules: Vec<(GlobMatcher, &'static str, FileRuleKind)>,
+    // with an include rule, files matching no rule are left out
+    has_include: bool,
+    // files not decided by a rule must have one of `types` and none of `excluded_types`
+    types: TypeMatcher,
+    excluded_types: TypeMatcher,
+    is_debug: bool,
+}
+
+impl FileFilter {
+    /// Returns the glob, type name or type definition that is not valid as the error.
+    pub fn new(config: &GrepConfig) -> Result<FileFilter, String> {
+        let types = Types::new(&config.type_additions)?;
+        let mut rules = Vec::new();
+        for rule in &config.file_rules {
+            let matcher = match GlobBuilder::new(&rule.glob)
+                .literal_separator(true)
+                .backslash_escape(true)
+                .case_insensitive(rule.is_case_insensitive)
+                .build()
+            {
+                Ok(glob) => glob.compile_matcher(),
+                Err(_err) => return Err(rule.glob.clone()),
+            };
+            rules.push((matcher, rule.glob_option(), rule.kind));
+        }
+        Ok(FileFilter {
+            has_include: config
+                .file_rules
+                .iter()
+                .any(|rule| rule.kind == FileRuleKind::Include),
+            rules,
+            types: types.matcher(&config.types)?,
+            excluded_types: types.matcher(&config.excluded_types)?,
+            is_debug: config.options.contains(&GrepOption::Debug),
+        })
+    }
+    /// Whether the file at `root.join(relative)`, found below `root` by a walk or glob,
+    /// is searched. As in a `.gitignore`, the last rule that matches decides; a glob
+    /// without a `/` matches the file name, any other the path relative to `root`.
+    /// Only then do the types count.
+    pub fn is_file_selected(&self, root: &Path, relative: &Path) -> bool {
+        let path = root.join(relative);
+        let rule = self.rules.iter().rev().find(|(matcher, _, kind)| {
+            *kind != FileRuleKind::ExcludeDir && is_match(matcher, relative)
+        });
+        match rule {
+            Some((_, _, FileRuleKind::Include)) => true,
+            Some((matcher, option, _)) => {
+                self.skip(&path, &format!("excluded by {} {}", option, matcher.glob()));
+                false
+            }
+            None if self.has_include => {
+                self.skip(&path, "not matched by any --include");
+                false
+            }
+            None if !self.excluded_types.is_empty() && self.excluded_types.is_match(&path) => {
+                let reason = format!("excluded by -T {}", self.excluded_types.names());
+                self.skip(&path, &reason);
+                false
+            }
+            None if !self.types.is_empty() && !self.types.is_match(&path) => {
+                self.skip(&path, &format!("not of type {}", self.types.names()));
+                false
+            }
+            None => true,
+        }
+    }
+    /// Whether the files in the directory at `root.join(relative)` are searched.
+    pub fn is_dir_selected(&self, root: &Path, relative: &Path) -> bool {
+        let rule = self.rules.iter().rev().find(|(matcher, _, kind)| {
+            *kind == FileRuleKind::ExcludeDir && is_match(matcher, relative)
+        });
+        match rule {
+            Some((matcher, option, _)) => {
+                self.skip(
+                    &root.join(relative),
+                    &format!("excluded by {} {}", option, matcher.glob()),
+                );
+                false
+            }
+            None => true,
+        }
+    }
+    /// Whether the file at `root.join(relative)` is searched, with the directories between them.
+    pub fn is_selected_below(&self, root: &Path, relative: &Path) -> bool {
+        let mut dir = PathBuf::new();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            dir.push(component);
+            if components.peek().is_none() {
+                return self.is_file_selected(root, &dir);
+            }
+            if !self.is_dir_selected(root, &dir) {
+                return false;
+            }
+        }
+        true
+    }
+    pub fn skip(&self, path: &Path, reason: &str) {
+        debug_skip(self.is_debug, path, reason);
+    }
+}
+
+/// Explains with `--debug` why `path` is not searched.
+pub fn debug_skip(is_debug: bool, path: &Path, reason: &str) {
+    if is_debug {
+        eprintln!("minigrep: skipping {}: {}", path.to_string_lossy(), reason);
+    }
+}
+
+impl FileRule {
+    fn glob_option(&self) -> &'static str {
+        match (self.kind, self.is_case_insensitive) {
+            (FileRuleKind::Include, false) => "--include",
+            (FileRuleKind::Exclude, false) => "--exclude",
+            (FileRuleKind::ExcludeDir, _) => "--exclude-dir",
+            (_, true) => "--iglob",
+        }
+    }
+}
+
+fn is_match(matcher: &GlobMatcher, relative: &Path) -> bool {
+    match matcher.glob().glob().contains('/') {
+        true => matcher.is_match(relative),
+        false => relative
+            .file_name()
+            .is_some_and(|name| matcher.is_match(Path::new(name))),
+    }
+}
@@ -0,0 +1,169 @@
+// --max-filesize, --newer-than, --older-than, --newer and --owner: filters on what
+// `fs::metadata` says about a file, checked before it is opened
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::grep_config::GrepConfig;
+
+/// Why the file at `path` is not searched, or `None` when it passes the filters.
+/// A file whose metadata cannot be read passes, so that opening it reports the error.
+pub fn skip_reason(config: &GrepConfig, path: &Path) -> Option<String> {
+    let has_filters = config.max_filesize.is_some()
+        || config.newer_than.is_some()
+        || config.older_than.is_some()
+        || config.owner.is_some();
+    if !has_filters {
+        return None;
+    }
+    let metadata = fs::metadata(path).ok()?;
+    if let Some(max_filesize) = config.max_filesize {
+        if metadata.len() > max_filesize {
+            return Some(format!("larger than {} bytes", max_filesize));
+        }
+    }
+    if config.newer_than.is_some() || config.older_than.is_some() {
+        let modified = metadata.modified().ok()?;
+        if config.newer_than.is_some_and(|time| modified <= time) {
+            return Some("not modified since --newer-than".to_string());
+        }
+        if config.older_than.is_some_and(|time| modified >= time) {
+            return Some("modified since --older-than".to_string());
+        }
+    }
+    if let Some(owner) = config.owner {
+        if owner_id(&metadata) != Some(owner) {
+            return Some(format!("not owned by user {}", owner));
+        }
+    }
+    None
+}
+
+/// Parses a size such as `4096`, `512K`, `10M` or `1G`, in powers of 1024.
+pub fn parse_size(value: &str) -> Option<u64> {
+    let (digits, multiplier) = match value.char_indices().last()? {
+        (i, 'k' | 'K') => (&value[..i], 1 << 10),
+        (i, 'm' | 'M') => (&value[..i], 1 << 20),
+        (i, 'g' | 'G') => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Parses either an age before now, such as `2h`, `30m`, `1d12h` or `2w`, or a UTC date
+/// such as `2024-01-31` or `2024-01-31T08:00:00`.
+pub fn parse_time(value: &str, now: SystemTime) -> Option<SystemTime> {
+    match parse_age(value) {
+        Some(age) => now.checked_sub(age),
+        None => parse_date(value),
+    }
+}
+
+/// The modification time of the file at `path`, for `--newer FILE`.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Parses a user name from `/etc/passwd` or a numeric user id.
+pub fn parse_owner(value: &str) -> Option<u32> {
+    if let Ok(uid) = value.parse() {
+        return Some(uid);
+    }
+    let passwd = fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(name), Some(_password), Some(uid)) if name == value => uid.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+fn parse_age(value: &str) -> Option<Duration> {
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        let count: u64 = number.parse().ok()?;
+        seconds = seconds.checked_add(count.checked_mul(unit)?)?;
+        number.clear();
+    }
+    // a trailing number without a unit is not an age
+    match number.is_empty() && !value.is_empty() {
+        true => Some(Duration::from_secs(seconds)),
+        false => None,
+    }
+}
+
+fn parse_date(value: &str) -> Option<SystemTime> {
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let mut numbers = date.splitn(3, '-').map(|number| number.parse::<u32>().ok());
+    let (year, month, day) = match (numbers.next(), numbers.next(), numbers.next()) {
+        (Some(Some(year)), Some(Some(month)), Some(Some(day))) => (year, month, day),
+        _ => return None,
+    };
+    if !(1970..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut seconds = days_since_epoch(year, month, day) * 24 * 60 * 60;
+    if let Some(time) = time {
+        let mut numbers = time.splitn(3, ':').map(|number| number.parse::<u64>().ok());
+        let (hours, minutes, secs) = match (numbers.next(), numbers.next(), numbers.next()) {
+            (Some(Some(hours)), Some(Some(minutes)), secs) => (hours, minutes, secs),
+            _ => return None,
+        };
+        let secs = match secs {
+            Some(Some(secs)) => secs,
+            Some(None) => return None,
+            None => 0,
+        };
+        if hours > 23 || minutes > 59 || secs > 59 {
+            return None;
+        }
+        seconds += hours * 60 * 60 + minutes * 60 + secs;
+    }
+    UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+/// Days from 1970-01-01 to the given date of the proleptic Gregorian calendar.
+fn days_since_epoch(year: u32, month: u32, day: u32) -> u64 {
+    // years start in March, so the leap day comes last
+    let year = if month <= 2 { year - 1 } else { year } as u64;
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month = month as u64;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(unix)]
+fn owner_id(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.uid())
+}
+
+#[cfg(not(unix))]
+fn owner_id(_metadata: &fs::Metadata) -> Option<u32> {
+    // without user ids no file has an owner to match
+    None
+}
@@ -0,0 +1,167 @@
+// the file types of -t, -T and --type-list: names for sets of globs, plus the interpreters
+// whose shebang gives a file without an extension its type
+
+use std::{collections::BTreeMap, fs::File, io::Read, path::Path};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+const DEFAULT_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"]),
+    ("css", &["*.css", "*.scss"]),
+    ("csv", &["*.csv", "*.tsv"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json", "*.jsonl", "*.ndjson"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("perl", &["*.pl", "*.pm"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("ruby", &["*.rb"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("web", &["*.{js,ts,html,css}"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+// interpreters named by `#!/bin/bash` or `#!/usr/bin/env python3`, without their version
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("bash", "sh"),
+    ("dash", "sh"),
+    ("ksh", "sh"),
+    ("node", "js"),
+    ("perl", "perl"),
+    ("python", "py"),
+    ("ruby", "ruby"),
+    ("sh", "sh"),
+    ("zsh", "sh"),
+];
+
+/// The built-in types with those of `--type-add`, each given as `name:glob`.
+pub struct Types {
+    globs: BTreeMap<String, Vec<String>>,
+}
+
+impl Types {
+    /// Returns the first invalid definition as the error.
+    pub fn new(additions: &[String]) -> Result<Types, String> {
+        let mut globs: BTreeMap<String, Vec<String>> = DEFAULT_TYPES
+            .iter()
+            .map(|(name, globs)| {
+                (
+                    name.to_string(),
+                    globs.iter().map(|glob| glob.to_string()).collect(),
+                )
+            })
+            .collect();
+        for addition in additions {
+            let (name, glob) = match addition.split_once(':') {
+                Some((name, glob)) if is_type_name(name) => (name, glob),
+                _ => return Err(addition.clone()),
+            };
+            if glob.is_empty() || Glob::new(glob).is_err() {
+                return Err(addition.clone());
+            }
+            globs
+                .entry(name.to_string())
+                .or_default()
+                .push(glob.to_string());
+        }
+        Ok(Types { globs })
+    }
+    /// One line per type, such as `rust: *.rs`, for `--type-list`.
+    pub fn lines(&self) -> Vec<String> {
+        self.globs
+            .iter()
+            .map(|(name, globs)| format!("{}: {}", name, globs.join(", ")))
+            .collect()
+    }
+    /// Matches the files of the types `names`; returns an unknown name as the error.
+    pub fn matcher(&self, names: &[String]) -> Result<TypeMatcher, String> {
+        let mut builder = GlobSetBuilder::new();
+        for name in names {
+            let globs = match self.globs.get(name) {
+                Some(x) => x,
+                None => return Err(name.clone()),
+            };
+            for glob in globs {
+                // checked by `new`
+                if let Ok(glob) = Glob::new(glob) {
+                    builder.add(glob);
+                }
+            }
+        }
+        let globs = match builder.build() {
+            Ok(x) => x,
+            Err(err) => return Err(err.to_string()),
+        };
+        Ok(TypeMatcher {
+            names: names.to_vec(),
+            globs,
+        })
+    }
+}
+
+pub struct TypeMatcher {
+    names: Vec<String>,
+    globs: GlobSet,
+}
+
+impl TypeMatcher {
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+    /// Whether the file at `path` has one of the types, by its name or, when that has no
+    /// extension, by the interpreter in its shebang line.
+    pub fn is_match(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(x) => x,
+            None => return false,
+        };
+        if self.globs.is_match(name) {
+            return true;
+        }
+        if Path::new(name).extension().is_some() {
+            return false;
+        }
+        match shebang_type(path) {
+            Some(file_type) => self.names.iter().any(|name| name == file_type),
+            None => false,
+        }
+    }
+    pub fn names(&self) -> String {
+        self.names.join(", ")
+    }
+}
+
+fn is_type_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The type of the interpreter in the shebang line of the file at `path`, if any.
+fn shebang_type(path: &Path) -> Option<&'static str> {
+    let mut buffer = [0; 128];
+    let num_bytes = File::open(path)
+        .and_then(|mut f| f.read(&mut buffer))
+        .ok()?;
+    let line = buffer[..num_bytes].strip_prefix(b"#!")?;
+    let line = String::from_utf8_lossy(line.split(|&b| b == b'\n').next()?).into_owned();
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // `env -S python3 -u` and the like
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(interpreter, _)| *interpreter == program)
+        .map(|(_, file_type)| *file_type)
+}
@@ -0,0 +1,134 @@
+// shell-style globs for filename operands: `*`, `?`, `[a-z]`, `{a,b}` and `**`
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+
+use super::file_filter::FileFilter;
+
+/// Whether `pattern` has glob syntax, so that it is expanded rather than taken as a path.
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// The files matching `pattern` that `filter` selects, in sorted order, or `None` when
+/// no file matches at all.
+///
+/// `*`, `?` and classes never match a `/`, while a `**` component matches any number of
+/// directories. Like a shell, wildcards skip hidden files and directories unless the
+/// pattern spells out a leading dot. Symlinks to files match, but symlinked directories
+/// are not entered.
+pub fn expand(pattern: &str, filter: &FileFilter) -> Result<Option<Vec<PathBuf>>, String> {
+    let components: Vec<&str> = pattern.split('/').collect();
+    // the directories before the first wildcard are read as they are
+    let num_literal = match components.iter().position(|component| is_glob(component)) {
+        Some(x) => x,
+        None => components.len(),
+    };
+    let base = match components[..num_literal].join("/") {
+        base if base.is_empty() && num_literal > 0 => "/".to_string(),
+        base => base,
+    };
+    let rest = &components[num_literal..];
+    let matcher = match GlobBuilder::new(&rest.join("/"))
+        .literal_separator(true)
+        .backslash_escape(true)
+        .build()
+    {
+        Ok(glob) => glob.compile_matcher(),
+        Err(err) => return Err(format!("{}: {}", pattern, err)),
+    };
+    let mut walk = Walk {
+        matcher: Some(matcher),
+        max_depth: match rest.contains(&"**") {
+            true => None,
+            false => Some(rest.len()),
+        },
+        allow_hidden: rest.iter().any(|component| component.starts_with('.')),
+        filter,
+        num_found: 0,
+        selected: Vec::new(),
+    };
+    walk.walk(Path::new(&base), Path::new(""), 1);
+    match walk.num_found {
+        0 => Ok(None),
+        _ => Ok(Some(walk.selected)),
+    }
+}
+
+/// The files below `dir` that `filter` selects, in sorted order. Hidden files and
+/// directories are skipped, and so are symlinked directories.
+pub fn walk_dir(dir: &Path, filter: &FileFilter) -> Vec<PathBuf> {
+    let mut walk = Walk {
+        matcher: None,
+        max_depth: None,
+        allow_hidden: false,
+        filter,
+        num_found: 0,
+        selected: Vec::new(),
+    };
+    walk.walk(dir, Path::new(""), 1);
+    walk.selected
+}
+
+struct Walk<'a> {
+    // `None` when every file is wanted
+    matcher: Option<GlobMatcher>,
+    // `None` when a `**` can match at any depth
+    max_depth: Option<usize>,
+    allow_hidden: bool,
+    filter: &'a FileFilter,
+    // files found before filtering
+    num_found: usize,
+    selected: Vec<PathBuf>,
+}
+
+impl Walk<'_> {
+    /// Adds the files below `base.join(dir)`, as `base` joined with their path.
+    fn walk(&mut self, base: &Path, dir: &Path, depth: usize) {
+        if self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return;
+        }
+        let full_dir = match base.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => base,
+        }
+        .join(dir);
+        // an unreadable directory has nothing to match, as in a shell
+        let entries = match fs::read_dir(full_dir) {
+            Ok(entries) => entries,
+            Err(_err) => return,
+        };
+        let mut entries: Vec<fs::DirEntry> = entries.flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let relative = dir.join(entry.file_name());
+            let path = base.join(&relative);
+            if !self.allow_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+                self.filter.skip(&path, "hidden");
+                continue;
+            }
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if is_dir {
+                if self.filter.is_dir_selected(base, &relative) {
+                    self.walk(base, &relative, depth + 1);
+                }
+                continue;
+            }
+            let is_match = match &self.matcher {
+                Some(matcher) => matcher.is_match(&relative),
+                None => true,
+            };
+            if !is_match || !entry.path().is_file() {
+                continue;
+            }
+            self.num_found += 1;
+            if self.filter.is_file_selected(base, &relative) {
+                self.selected.push(path);
+            }
+        }
+    }
+}
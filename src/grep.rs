@@ -1,81 +1,208 @@
 use std::{
+    cell::{Cell, RefCell},
     collections::{HashSet, VecDeque},
-    fs::File,
-    io::{BufRead, BufReader},
-    path,
+    error, fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    ops, path, slice,
+    time::{Duration, Instant},
 };
 
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 
-use super::grep_config::{self, GrepConfig, GrepOption};
+use super::archive::{self, ArchiveError, ArchiveLimits};
+use super::case;
+use super::checkpoint::{self, Checkpoint};
+use super::file_filter::{self, FileFilter};
+use super::file_meta;
+use super::grep_config::{self, GrepConfig, GrepOption, TableFormat};
+use super::index;
+use super::json_output;
+use super::json_path::{self, Selector};
+use super::table;
+
+mod follow;
+pub use follow::follow;
 
 #[derive(Debug)]
 pub enum GrepError {
-    FileNotExists,
-    NotValidUTF8,
-    Query(String),
+    // a file that could not be opened or read, and why
+    File(path::PathBuf, io::Error),
+    NotValidUTF8(path::PathBuf),
+    Query(regex::Error),
+    Archive(String),
+    // a CSV or TSV file that could not be parsed or lacks a `--field`, and why
+    Table(path::PathBuf, String),
+    // a file under `--json-path` that is not valid JSON, and why
+    JsonPath(path::PathBuf, String),
+    // a `--json-path` selector that could not be parsed
+    Selector(String),
+    Write(String),
+    StateFile(String),
+    Index(String),
+}
+
+impl fmt::Display for GrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GrepError::File(path, err) => write!(f, "{}: {}", path.to_string_lossy(), err),
+            GrepError::NotValidUTF8(path) => write!(
+                f,
+                "{}: file contents not being valid utf-8",
+                path.to_string_lossy()
+            ),
+            GrepError::Query(x) => write!(f, "Query error: {}", x),
+            GrepError::Archive(x) => write!(f, "Archive error: {}", x),
+            GrepError::Table(path, x) => {
+                write!(f, "Table error: {}: {}", path.to_string_lossy(), x)
+            }
+            GrepError::JsonPath(path, x) => {
+                write!(f, "JSON error: {}: {}", path.to_string_lossy(), x)
+            }
+            GrepError::Selector(x) => write!(f, "JSON path error: {}", x),
+            GrepError::Write(x) => write!(f, "Write error: {}", x),
+            GrepError::StateFile(x) => write!(f, "State file error: {}", x),
+            GrepError::Index(x) => write!(f, "Index error: {}", x),
+        }
+    }
 }
 
-struct LineResult {
+impl error::Error for GrepError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            GrepError::File(_, err) => Some(err),
+            GrepError::Query(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A selected line, or a context line around one.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LineResult {
+    // the file the line was found in, with any archive members after `!`
+    path: path::PathBuf,
     line_number: i32,
+    // offset of the first byte of the line within the file
+    byte_offset: u64,
     text: String,
+    // the line ending stripped from `text`
+    terminator: String,
+    // false for context lines
+    is_match: bool,
+    // matches within `text`
+    submatches: Vec<Submatch>,
+}
+
+/// One match within the text of a line.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Submatch {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    // index of the query that matched, in the order they were given
+    pub(crate) pattern_index: usize,
+    // the match after expanding the `--replace` template
+    pub(crate) replacement: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineKind {
+    // a selected line; with -v it has no matches
+    Match,
+    // a line of -A, -B or -C context, or a kept table header
+    Context,
 }
 
-struct FileResult {
+/// The lines found in one file, or in one member of an archive.
+#[derive(Clone, Debug)]
+pub struct FileResult {
     file_path: path::PathBuf,
+    // paths of the archive members leading to this file, outermost first
+    members: Vec<String>,
     lines: Vec<LineResult>,
+    bytes_searched: u64,
+    elapsed: Duration,
 }
 
+#[derive(Debug)]
 pub struct GrepResult {
     file_results: Vec<FileResult>,
+    // the files that could not be searched; the others were searched all the same
+    errors: Vec<GrepError>,
+    elapsed: Duration,
+    is_only_matching: bool,
 }
 
 impl GrepResult {
     fn check_rep(&self) {}
     pub fn new(config: &grep_config::GrepConfig) -> Result<GrepResult, GrepError> {
+        let start = Instant::now();
         let mut file_results = Vec::new();
-        let mut queries = Vec::new();
-        for query_str in &config.queries {
-            let mut query_str = query_str.clone();
-            // case insensitive
-            if config.options.contains(&GrepOption::CaseInsensitive) {
-                query_str = query_str.to_lowercase();
-            }
-            // word
-            if config.options.contains(&GrepOption::Word) {
-                let mut tmp = "\\W".to_string();
-                tmp.push_str(&query_str);
-                tmp.push_str("\\W");
-                query_str = tmp;
-            }
-            let re = match Regex::new(&query_str) {
-                Ok(x) => x,
-                Err(_) => return Err(GrepError::Query(query_str)),
-            };
-            queries.push(re);
-        }
-        for filename in &config.filenames {
-            let single_file_config = GrepSingleFileConfig {
-                options: &config.options,
-                queries: &queries,
-                filename: &filename,
-                num_pre_lines: config.num_pre_lines,
-                num_post_lines: config.num_post_lines,
-            };
-            let file_result = FileResult::new(&single_file_config)?;
-            file_results.push(file_result);
-        }
-        let grep_result = GrepResult { file_results };
+        let mut errors = Vec::new();
+        search_files(
+            config,
+            &never,
+            &mut |file_result| {
+                file_results.push(file_result);
+                Ok(())
+            },
+            &mut |err| errors.push(err),
+        )?;
+        let grep_result = GrepResult {
+            file_results,
+            errors,
+            elapsed: start.elapsed(),
+            is_only_matching: config.options.contains(&GrepOption::OnlyMatching),
+        };
         grep_result.check_rep();
-        return Ok(grep_result);
+        Ok(grep_result)
+    }
+    /// The errors of the files that could not be searched.
+    pub fn errors(&self) -> &[GrepError] {
+        &self.errors
+    }
+    /// Whether any line was selected, which makes the exit status 0 rather than 1.
+    pub fn has_match(&self) -> bool {
+        self.file_results.iter().any(FileResult::has_match)
+    }
+    /// Every file that was searched, in order, including those without selected lines.
+    pub fn files(&self) -> slice::Iter<'_, FileResult> {
+        self.file_results.iter()
+    }
+    /// Every line of every file, each with the file it was found in.
+    pub fn lines(&self) -> impl Iterator<Item = (&FileResult, &LineResult)> {
+        self.file_results.iter().flat_map(|file_result| {
+            file_result
+                .lines
+                .iter()
+                .map(move |line| (file_result, line))
+        })
     }
     pub fn to_string(&self, config: &GrepConfig) -> String {
+        if config.options.contains(&GrepOption::Json) {
+            return self.to_json_lines().join("\n");
+        }
         let mut s = String::new();
-        let lines = self.to_lines(config.options.contains(&GrepOption::LineNumber));
         if config.options.contains(&GrepOption::Count) {
-            s.push_str(&lines.len().to_string());
+            // context lines and kept header rows are not counted
+            let count: usize = self
+                .file_results
+                .iter()
+                .map(|file_result| {
+                    file_result
+                        .lines
+                        .iter()
+                        .filter(|line| line.is_match)
+                        .count()
+                })
+                .sum();
+            s.push_str(&count.to_string());
             return s;
         }
+        // selected JSON values are always reported with their line number
+        let lines = self.to_lines(
+            config.options.contains(&GrepOption::LineNumber) || config.json_path.is_some(),
+        );
         for line in lines {
             s.push_str(&line);
             s.push('\n');
@@ -83,35 +210,442 @@ impl GrepResult {
         if s.ends_with('\n') {
             s.pop();
         }
-        return s;
+        s
     }
     pub fn to_lines(&self, is_with_line_num: bool) -> Vec<String> {
         let mut matches = Vec::new();
         for file_result in &self.file_results {
+            let name = if self.file_results.len() > 1 || !file_result.members.is_empty() {
+                Some(file_result.display_path())
+            } else {
+                None
+            };
             for line in &file_result.lines {
-                let mut hinted_line = String::new();
-                if self.file_results.len() > 1 {
-                    hinted_line.push_str(
-                        &file_result
-                            .file_path
-                            .file_name()
-                            .expect("impossible")
-                            .to_str()
-                            .unwrap()
-                            .to_string(),
-                    );
-                    hinted_line.push_str(":");
+                matches.append(&mut line.to_lines(
+                    name.as_deref(),
+                    is_with_line_num,
+                    self.is_only_matching,
+                ));
+            }
+        }
+        matches
+    }
+    /// One JSON message per line, in the same shape as ripgrep's `--json` output.
+    pub fn to_json_lines(&self) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut total = json_output::Stats::default();
+        for file_result in &self.file_results {
+            messages.append(&mut file_result.to_json_lines(&mut total));
+        }
+        messages.push(json_output::summary(self.elapsed, &total));
+        messages
+    }
+}
+
+impl LineResult {
+    /// The file the line was found in; for an archive member, `archive!member`.
+    pub fn path(&self) -> &path::Path {
+        &self.path
+    }
+    /// The 1-based number of the line; for a JSON value, of the line it starts on.
+    pub fn line_number(&self) -> i32 {
+        self.line_number
+    }
+    /// The offset of the first byte of the line within the file.
+    pub fn byte_offset(&self) -> u64 {
+        self.byte_offset
+    }
+    /// The line without its line ending.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn kind(&self) -> LineKind {
+        match self.is_match {
+            true => LineKind::Match,
+            false => LineKind::Context,
+        }
+    }
+    /// The matches within `text`, leftmost first. For a table record, the matches in its
+    /// selected fields, spanning the raw record: an escaped `""` in a match covers both quotes.
+    pub fn submatches(&self) -> &[Submatch] {
+        &self.submatches
+    }
+    /// The printed form of the line: usually one line, one per match with `-o`.
+    fn to_lines(
+        &self,
+        name: Option<&str>,
+        is_with_line_num: bool,
+        is_only_matching: bool,
+    ) -> Vec<String> {
+        let mut hint = String::new();
+        if let Some(name) = name {
+            hint.push_str(name);
+            hint.push(':');
+        }
+        if is_with_line_num {
+            hint.push_str(&self.line_number.to_string());
+            hint.push(':');
+        }
+        if is_only_matching {
+            // each match on its own line; context lines are not printed
+            return self
+                .submatches
+                .iter()
+                .map(|submatch| {
+                    let text = match &submatch.replacement {
+                        Some(replacement) => replacement,
+                        None => &self.text[submatch.start..submatch.end],
+                    };
+                    format!("{}{}", hint, text)
+                })
+                .collect();
+        }
+        let mut hinted_line = hint;
+        hinted_line.push_str(&self.replaced_text());
+        vec![hinted_line]
+    }
+    /// The line with every match replaced, when a replacement template was given.
+    fn replaced_text(&self) -> String {
+        replace_submatches(&self.text, &self.submatches)
+    }
+}
+
+/// Whether any line of the files of `config` is selected. Like `grep -q`, the search
+/// ends at the first selected line. Files that cannot be searched go to `on_error`.
+pub fn is_match(
+    config: &GrepConfig,
+    on_error: &mut dyn FnMut(GrepError),
+) -> Result<bool, GrepError> {
+    let is_found = Cell::new(false);
+    search_files(
+        config,
+        &|| is_found.get(),
+        &mut |file_result| {
+            if file_result.has_match() {
+                is_found.set(true);
+            }
+            Ok(())
+        },
+        on_error,
+    )?;
+    Ok(is_found.get())
+}
+
+/// Searches every file of `config` and hands the result for each to `visit` in order.
+/// Stops early, without an error, once `should_stop` returns true.
+/// A file that cannot be searched goes to `on_error` instead, and the search goes on.
+fn search_files(
+    config: &GrepConfig,
+    should_stop: &dyn Fn() -> bool,
+    visit: &mut dyn FnMut(FileResult) -> Result<(), GrepError>,
+    on_error: &mut dyn FnMut(GrepError),
+) -> Result<(), GrepError> {
+    let queries = compile_queries(config)?;
+    let json_selector = match &config.json_path {
+        Some(selector) => match Selector::new(selector) {
+            Ok(x) => Some(x),
+            Err(err) => return Err(GrepError::Selector(err)),
+        },
+        None => None,
+    };
+    let mut checkpoints = match &config.state_file {
+        Some(state_file) => match checkpoint::load(state_file) {
+            Ok(x) => Some(x),
+            Err(err) => return Err(GrepError::StateFile(err)),
+        },
+        None => None,
+    };
+    let is_index_search = config.options.contains(&GrepOption::Index);
+    let mut filenames = Vec::new();
+    for filename in &config.filenames {
+        if is_index_search && filename.is_dir() {
+            let filter = match FileFilter::new(config) {
+                Ok(x) => x,
+                Err(glob) => return Err(GrepError::Index(format!("invalid glob: {}", glob))),
+            };
+            let candidates = index::candidates(
+                filename,
+                &queries,
+                config.options.contains(&GrepOption::CaseInsensitive)
+                    || config.options.contains(&GrepOption::PreserveCase),
+                config.options.contains(&GrepOption::Invert),
+            )?;
+            filenames.extend(candidates.into_iter().filter(|candidate| {
+                match candidate.strip_prefix(filename) {
+                    Ok(relative) => filter.is_selected_below(filename, relative),
+                    Err(_err) => true,
+                }
+            }));
+        } else {
+            filenames.push(filename.clone());
+        }
+    }
+    for filename in &filenames {
+        // the files searched so far still get their checkpoints
+        if should_stop() {
+            break;
+        }
+        if let Some(reason) = file_meta::skip_reason(config, filename) {
+            let is_debug = config.options.contains(&GrepOption::Debug);
+            file_filter::debug_skip(is_debug, filename, &reason);
+            continue;
+        }
+        let single_file_config = GrepSingleFileConfig::new(
+            config,
+            &queries,
+            json_selector.as_ref(),
+            filename,
+            should_stop,
+        );
+        // like the binary files of a tree the index has not seen yet, those found in
+        // directories and by globs are skipped; only a file named directly is reported
+        let is_skipped_binary = |err: &GrepError| {
+            matches!(err, GrepError::NotValidUTF8(_))
+                && (is_index_search || config.found_filenames.contains(filename))
+        };
+        if config.options.contains(&GrepOption::SearchArchives) && archive::is_archive(filename) {
+            let limits = ArchiveLimits {
+                max_depth: config.archive_max_depth,
+                max_ratio: config.archive_max_ratio,
+            };
+            let mut member_error = None;
+            let mut visit = |members: &[String], contents: &[u8]| {
+                // binary members are skipped rather than failing the whole archive
+                if std::str::from_utf8(contents).is_err() || member_error.is_some() {
+                    return;
+                }
+                match FileResult::from_reader(&single_file_config, members.to_vec(), contents) {
+                    Ok(file_result) => {
+                        if let Err(err) = visit(file_result) {
+                            member_error = Some(err);
+                        }
+                    }
+                    Err(err) if err.is_file_error() => on_error(err),
+                    Err(err) => member_error = Some(err),
+                }
+            };
+            match archive::for_each_member(filename, &limits, &mut visit) {
+                Ok(()) => {
+                    if let Some(err) = member_error {
+                        return Err(err);
+                    }
                 }
-                if is_with_line_num {
-                    hinted_line.push_str(&line.line_number.to_string());
-                    hinted_line.push_str(":");
+                Err(ArchiveError::Io(err)) => on_error(GrepError::File(filename.clone(), err)),
+                Err(ArchiveError::Corrupt(x)) => on_error(GrepError::Archive(x)),
+                Err(ArchiveError::RatioExceeded(x)) => on_error(GrepError::Archive(format!(
+                    "{}: decompressed size exceeds {} times the compressed size",
+                    x, config.archive_max_ratio
+                ))),
+            }
+            continue;
+        }
+        let (file_result, checkpoint) = match &checkpoints {
+            Some(checkpoints) => {
+                let key = checkpoint::key(filename);
+                match FileResult::resume(&single_file_config, checkpoints.get(&key)) {
+                    Ok((file_result, checkpoint)) => (file_result, Some((key, checkpoint))),
+                    Err(err) if is_skipped_binary(&err) => continue,
+                    Err(err) if err.is_file_error() => {
+                        on_error(err);
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            None => match FileResult::new(&single_file_config) {
+                Ok(x) => (x, None),
+                Err(err) if is_skipped_binary(&err) => continue,
+                Err(err) if err.is_file_error() => {
+                    on_error(err);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            },
+        };
+        // a search stopped halfway through a file leaves that file out, and its checkpoint
+        if should_stop() {
+            break;
+        }
+        if let (Some(checkpoints), Some((key, checkpoint))) = (&mut checkpoints, checkpoint) {
+            checkpoints.insert(key, checkpoint);
+        }
+        visit(file_result)?;
+    }
+    if let (Some(state_file), Some(checkpoints)) = (&config.state_file, &checkpoints) {
+        if let Err(err) = checkpoint::save(state_file, checkpoints) {
+            return Err(GrepError::StateFile(err));
+        }
+    }
+    Ok(())
+}
+
+/// Searches like `GrepResult::new` and writes the `--json` messages of each file to `out`
+/// as soon as the file is searched, so results arrive while the search goes on.
+/// Stops early, leaving out the summary, once `should_stop` returns true.
+pub fn stream_json(
+    config: &GrepConfig,
+    out: &mut dyn Write,
+    should_stop: &dyn Fn() -> bool,
+) -> Result<(), GrepError> {
+    let start = Instant::now();
+    let mut total = json_output::Stats::default();
+    let write_error = |err: io::Error| GrepError::Write(err.to_string());
+    // both closures write to `out`
+    let out = RefCell::new(out);
+    let mut write_error_message = None;
+    search_files(
+        config,
+        should_stop,
+        &mut |file_result| {
+            let mut out = out.borrow_mut();
+            for message in file_result.to_json_lines(&mut total) {
+                writeln!(out, "{}", message).map_err(write_error)?;
+            }
+            out.flush().map_err(write_error)
+        },
+        &mut |err| {
+            let message = json_output::error(&err.to_string());
+            if let Err(err) = writeln!(out.borrow_mut(), "{}", message) {
+                write_error_message.get_or_insert(write_error(err));
+            }
+        },
+    )?;
+    if let Some(err) = write_error_message {
+        return Err(err);
+    }
+    let out = out.into_inner();
+    if should_stop() {
+        return Ok(());
+    }
+    let summary = json_output::summary(start.elapsed(), &total);
+    writeln!(out, "{}", summary).map_err(write_error)?;
+    out.flush().map_err(write_error)
+}
+
+fn never() -> bool {
+    false
+}
+
+impl GrepError {
+    /// Whether the error concerns a single file, so the other files can still be searched.
+    fn is_file_error(&self) -> bool {
+        matches!(
+            self,
+            GrepError::File(..)
+                | GrepError::NotValidUTF8(_)
+                | GrepError::Archive(_)
+                | GrepError::Table(..)
+                | GrepError::JsonPath(..)
+        )
+    }
+}
+
+/// The error for reading the file at `path`: text that is not UTF-8 is reported as such.
+fn read_error(path: &path::Path, err: io::Error) -> GrepError {
+    match err.kind() {
+        io::ErrorKind::InvalidData => GrepError::NotValidUTF8(path.to_path_buf()),
+        _ => GrepError::File(path.to_path_buf(), err),
+    }
+}
+
+pub(crate) fn compile_queries(config: &GrepConfig) -> Result<Vec<Regex>, GrepError> {
+    let mut queries = Vec::new();
+    for query_str in &config.queries {
+        let mut query_str = query_str.clone();
+        // preserve case: match every case style of an identifier
+        let preserve_case = config.options.contains(&GrepOption::PreserveCase);
+        if preserve_case {
+            if let Some(pattern) = case::identifier_pattern(&query_str) {
+                query_str = pattern;
+            }
+        }
+        // word and case insensitive are left to the regex rather than done by wrapping the
+        // query in `\W` and lowercasing both it and the text, so that match offsets stay
+        // those of the original text and words at either end of a line match
+        if config.options.contains(&GrepOption::Word) {
+            query_str = format!("\\b(?:{})\\b", query_str);
+        }
+        let re = match RegexBuilder::new(&query_str)
+            .case_insensitive(
+                config.options.contains(&GrepOption::CaseInsensitive) || preserve_case,
+            )
+            .build()
+        {
+            Ok(x) => x,
+            Err(err) => return Err(GrepError::Query(err)),
+        };
+        queries.push(re);
+    }
+    Ok(queries)
+}
+
+pub(crate) fn replace_submatches(text: &str, submatches: &[Submatch]) -> String {
+    let mut replaced = String::new();
+    let mut last = 0;
+    for submatch in submatches {
+        if let Some(replacement) = &submatch.replacement {
+            replaced.push_str(&text[last..submatch.start]);
+            replaced.push_str(replacement);
+            last = submatch.end;
+        }
+    }
+    replaced.push_str(&text[last..]);
+    replaced
+}
+
+/// Finds the non-overlapping matches of all queries in `text`, leftmost first.
+/// Each match is expanded with `template` when one is given; `$1` and `${name}`
+/// refer to the capture groups of the query that matched. With `preserve_case`
+/// the expansion takes the case shape of the text it replaces.
+pub(crate) fn find_matches(
+    queries: &[Regex],
+    text: &str,
+    template: Option<&str>,
+    preserve_case: bool,
+) -> Vec<Submatch> {
+    let mut matches = Vec::new();
+    let mut pos = 0;
+    while pos <= text.len() {
+        let mut best: Option<(usize, usize, usize)> = None;
+        for (index, query) in queries.iter().enumerate() {
+            if let Some(m) = query.find_at(text, pos) {
+                if best.is_none_or(|(start, _, _)| m.start() < start) {
+                    best = Some((m.start(), m.end(), index));
                 }
-                hinted_line.push_str(&line.text);
-                matches.push(hinted_line);
             }
         }
-        return matches;
+        let (start, end, index) = match best {
+            Some(x) => x,
+            None => break,
+        };
+        if end > start {
+            let replacement = template.map(|template| {
+                let captures = queries[index]
+                    .captures_at(text, pos)
+                    .expect("the query matched at this position");
+                let mut replacement = String::new();
+                captures.expand(template, &mut replacement);
+                if preserve_case {
+                    replacement = case::preserve_case(&text[start..end], &replacement);
+                }
+                replacement
+            });
+            matches.push(Submatch {
+                start,
+                end,
+                pattern_index: index,
+                replacement,
+            });
+            pos = end;
+        } else {
+            // empty matches are not reported; step over the next character
+            pos = match text[end..].chars().next() {
+                Some(c) => end + c.len_utf8(),
+                None => break,
+            };
+        }
     }
+    matches
 }
 
 struct GrepSingleFileConfig<'a> {
@@ -120,82 +654,568 @@ struct GrepSingleFileConfig<'a> {
     filename: &'a path::PathBuf,
     num_pre_lines: usize,
     num_post_lines: usize,
+    table_format: &'a Option<TableFormat>,
+    fields: &'a Vec<String>,
+    json_selector: Option<&'a Selector>,
+    replacement: Option<&'a str>,
+    // checked between lines, so a long file can be given up halfway
+    should_stop: &'a dyn Fn() -> bool,
 }
 
-impl FileResult {
-    fn check_rep(&self) {}
-    fn new(config: &GrepSingleFileConfig) -> Result<FileResult, GrepError> {
-        let f = match File::open(config.filename) {
-            Ok(f) => f,
-            Err(_err) => return Err(GrepError::FileNotExists),
-        };
-        let mut reader = BufReader::new(f);
-        let mut buffer = String::new();
-        let mut matches = Vec::new();
-        let mut line_number = 1;
-        let mut prev_unmatched_lines = VecDeque::new();
-        let mut post_lines_left = 0;
+impl<'a> GrepSingleFileConfig<'a> {
+    fn new(
+        config: &'a GrepConfig,
+        queries: &'a Vec<Regex>,
+        json_selector: Option<&'a Selector>,
+        filename: &'a path::PathBuf,
+        should_stop: &'a dyn Fn() -> bool,
+    ) -> GrepSingleFileConfig<'a> {
+        GrepSingleFileConfig {
+            options: &config.options,
+            queries,
+            filename,
+            num_pre_lines: config.num_pre_lines,
+            num_post_lines: config.num_post_lines,
+            table_format: &config.table_format,
+            fields: &config.fields,
+            json_selector,
+            replacement: config.replacement.as_deref(),
+            should_stop,
+        }
+    }
+}
+
+/// Collects selected lines together with their surrounding context lines.
+struct ContextWindow {
+    num_pre_lines: usize,
+    num_post_lines: usize,
+    prev_unmatched_lines: VecDeque<LineResult>,
+    post_lines_left: usize,
+    lines: Vec<LineResult>,
+}
+
+impl ContextWindow {
+    fn new(num_pre_lines: usize, num_post_lines: usize) -> ContextWindow {
+        ContextWindow {
+            num_pre_lines,
+            num_post_lines,
+            prev_unmatched_lines: VecDeque::new(),
+            post_lines_left: 0,
+            lines: Vec::new(),
+        }
+    }
+    fn push(&mut self, line_result: LineResult) {
+        if line_result.is_match {
+            while !self.prev_unmatched_lines.is_empty() {
+                self.lines
+                    .push(self.prev_unmatched_lines.pop_front().expect("impossible"));
+            }
+            self.lines.push(line_result);
+            self.post_lines_left = self.num_post_lines;
+        } else {
+            if self.post_lines_left > 0 {
+                self.lines.push(line_result);
+                self.post_lines_left -= 1;
+            } else {
+                self.prev_unmatched_lines.push_back(line_result);
+                while self.prev_unmatched_lines.len() > self.num_pre_lines {
+                    self.prev_unmatched_lines.pop_front();
+                }
+            }
+        }
+    }
+}
+
+/// Matches lines one at a time; selected lines and their context collect in `window.lines`.
+struct LineSearcher<'a, 'b> {
+    config: &'b GrepSingleFileConfig<'a>,
+    // the path given to each line
+    path: path::PathBuf,
+    window: ContextWindow,
+    // position of the next line
+    line_number: i32,
+    byte_offset: u64,
+}
 
+impl<'a, 'b> LineSearcher<'a, 'b> {
+    fn new(
+        config: &'b GrepSingleFileConfig<'a>,
+        path: path::PathBuf,
+        line_number: i32,
+        byte_offset: u64,
+    ) -> LineSearcher<'a, 'b> {
+        LineSearcher {
+            config,
+            path,
+            window: ContextWindow::new(config.num_pre_lines, config.num_post_lines),
+            line_number,
+            byte_offset,
+        }
+    }
+    /// Searches every line of `reader`; with `is_complete_only`, a last line without
+    /// a line ending is not searched.
+    fn push_lines<R: BufRead>(
+        &mut self,
+        reader: &mut R,
+        is_complete_only: bool,
+    ) -> Result<(), GrepError> {
+        let mut buffer = String::new();
         while match reader.read_line(&mut buffer) {
             Ok(num_bytes) => num_bytes,
-            Err(_err) => return Err(GrepError::NotValidUTF8),
+            Err(err) => return Err(read_error(self.config.filename, err)),
         } > 0
         {
-            // trim trailing newline
-            if buffer.ends_with('\n') {
-                // remove the last `\n`
+            if is_complete_only && !buffer.ends_with('\n') {
+                break;
+            }
+            if (self.config.should_stop)() {
+                break;
+            }
+            self.push_line(&buffer);
+            // clear buffer for next line
+            buffer.clear();
+            // with -q, the first selected line settles the outcome
+            if self.config.options.contains(&GrepOption::Quiet)
+                && self.window.lines.last().is_some_and(|line| line.is_match)
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+    /// `line` is one line of input including its line ending.
+    fn push_line(&mut self, line: &str) {
+        let config = self.config;
+        let mut buffer = line.to_string();
+        // trim trailing newline
+        let mut terminator = String::new();
+        if buffer.ends_with('\n') {
+            // remove the last `\n`
+            buffer.pop();
+            terminator.push('\n');
+
+            if buffer.ends_with('\r') {
+                // for Windows
                 buffer.pop();
+                terminator.insert(0, '\r');
+            }
+        }
+        // pattern matching
+        let mut is_matched = false;
+        for query in config.queries {
+            if query.is_match(&buffer) {
+                is_matched = true;
+                break;
+            }
+        }
+        // invert, context
+        let is_selected = is_matched != config.options.contains(&GrepOption::Invert);
+        let submatches = if is_selected && is_matched {
+            find_matches(
+                config.queries,
+                &buffer,
+                config.replacement,
+                config.options.contains(&GrepOption::PreserveCase),
+            )
+        } else {
+            Vec::new()
+        };
+        self.window.push(LineResult {
+            path: self.path.clone(),
+            line_number: self.line_number,
+            byte_offset: self.byte_offset,
+            text: buffer,
+            terminator,
+            is_match: is_selected,
+            submatches,
+        });
+        self.line_number += 1;
+        self.byte_offset += line.len() as u64;
+    }
+}
 
-                if buffer.ends_with('\r') {
-                    // for Windows
-                    buffer.pop();
-                }
+impl Submatch {
+    /// The byte range of the match within the text of its line.
+    pub fn span(&self) -> ops::Range<usize> {
+        self.start..self.end
+    }
+    /// The index of the query that matched, in the order the queries were given.
+    pub fn pattern_index(&self) -> usize {
+        self.pattern_index
+    }
+    /// The match after expanding the `--replace` template, when one was given.
+    pub fn replacement(&self) -> Option<&str> {
+        self.replacement.as_deref()
+    }
+}
+
+impl FileResult {
+    fn check_rep(&self) {}
+    /// The file that was searched; for an archive member, the archive.
+    pub fn path(&self) -> &path::Path {
+        &self.file_path
+    }
+    /// The paths of the archive members leading to this file, outermost first.
+    pub fn members(&self) -> &[String] {
+        &self.members
+    }
+    pub fn lines(&self) -> slice::Iter<'_, LineResult> {
+        self.lines.iter()
+    }
+    pub fn has_match(&self) -> bool {
+        self.lines.iter().any(|line| line.is_match)
+    }
+    fn new(config: &GrepSingleFileConfig) -> Result<FileResult, GrepError> {
+        let f = match File::open(config.filename) {
+            Ok(f) => f,
+            Err(err) => return Err(GrepError::File(config.filename.clone(), err)),
+        };
+        FileResult::from_reader(config, Vec::new(), BufReader::new(f))
+    }
+    fn from_reader<R: BufRead>(
+        config: &GrepSingleFileConfig,
+        members: Vec<String>,
+        mut reader: R,
+    ) -> Result<FileResult, GrepError> {
+        if let Some(selector) = config.json_selector {
+            return FileResult::from_json(config, members, reader, selector);
+        }
+        if let Some(format) = config.table_format {
+            let mut contents = String::new();
+            if let Err(err) = reader.read_to_string(&mut contents) {
+                return Err(read_error(config.filename, err));
             }
-            let original_buffer = buffer.clone();
-            // case insensitive
-            if config.options.contains(&GrepOption::CaseInsensitive) {
-                buffer = buffer.to_lowercase();
-            }
-            // pattern matching
-            let mut is_matched = false;
-            for query in config.queries {
-                if query.is_match(&buffer) {
-                    is_matched = true;
-                    break;
+            return FileResult::from_table(config, members, &contents, format);
+        }
+        let start = Instant::now();
+        let path = member_path(config.filename, &members);
+        let mut searcher = LineSearcher::new(config, path, 1, 0);
+        searcher.push_lines(&mut reader, false)?;
+
+        let file_result = FileResult {
+            file_path: config.filename.clone(),
+            members,
+            lines: searcher.window.lines,
+            bytes_searched: searcher.byte_offset,
+            elapsed: start.elapsed(),
+        };
+        file_result.check_rep();
+        Ok(file_result)
+    }
+    /// Searches the lines after `checkpoint` and returns where the next run should resume.
+    /// The file is searched from its start when it was replaced or truncated since then,
+    /// even when it grew back past the checkpoint, which its fingerprint tells.
+    /// A last line without a line ending may still be being written, so it is left for later.
+    fn resume(
+        config: &GrepSingleFileConfig,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Result<(FileResult, Checkpoint), GrepError> {
+        let start = Instant::now();
+        let file_error = |err| GrepError::File(config.filename.clone(), err);
+        let mut f = match File::open(config.filename) {
+            Ok(f) => f,
+            Err(err) => return Err(file_error(err)),
+        };
+        let metadata = match f.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) => return Err(file_error(err)),
+        };
+        let id = file_id(&metadata);
+        let is_resumable = match checkpoint {
+            Some(checkpoint)
+                if checkpoint.file_id == id && checkpoint.byte_offset <= metadata.len() =>
+            {
+                match checkpoint::fingerprint(&mut f, checkpoint.byte_offset) {
+                    Ok(fingerprint) => fingerprint == checkpoint.fingerprint,
+                    Err(err) => return Err(file_error(err)),
                 }
             }
-            // invert, context
-            let line_result = LineResult {
-                line_number,
-                text: original_buffer,
+            _ => false,
+        };
+        let (line_number, byte_offset) = match checkpoint {
+            Some(checkpoint) if is_resumable => (checkpoint.line_number, checkpoint.byte_offset),
+            _ => (1, 0),
+        };
+        if let Err(err) = f.seek(SeekFrom::Start(byte_offset)) {
+            return Err(file_error(err));
+        }
+        let mut searcher =
+            LineSearcher::new(config, config.filename.clone(), line_number, byte_offset);
+        let mut reader = BufReader::new(f);
+        searcher.push_lines(&mut reader, true)?;
+
+        let fingerprint = match checkpoint::fingerprint(reader.get_mut(), searcher.byte_offset) {
+            Ok(x) => x,
+            Err(err) => return Err(file_error(err)),
+        };
+        let checkpoint = Checkpoint {
+            file_id: id,
+            byte_offset: searcher.byte_offset,
+            line_number: searcher.line_number,
+            fingerprint,
+        };
+        let file_result = FileResult {
+            file_path: config.filename.clone(),
+            members: Vec::new(),
+            lines: searcher.window.lines,
+            bytes_searched: searcher.byte_offset - byte_offset,
+            elapsed: start.elapsed(),
+        };
+        file_result.check_rep();
+        Ok((file_result, checkpoint))
+    }
+    /// Searches a CSV or TSV document record by record, matching only the selected columns.
+    fn from_table(
+        config: &GrepSingleFileConfig,
+        members: Vec<String>,
+        contents: &str,
+        format: &TableFormat,
+    ) -> Result<FileResult, GrepError> {
+        let start = Instant::now();
+        let path = member_path(config.filename, &members);
+        let table_error = |err| GrepError::Table(path.clone(), err);
+        let records = match table::read_records(contents, format) {
+            Ok(x) => x,
+            Err(err) => return Err(table_error(err)),
+        };
+        let mut window = ContextWindow::new(config.num_pre_lines, config.num_post_lines);
+        let mut records = records.into_iter();
+        let keep_header = config.options.contains(&GrepOption::KeepHeader);
+        let header = if table::has_header(keep_header, config.fields) {
+            records.next()
+        } else {
+            None
+        };
+        let columns = match table::select_columns(header.as_ref(), config.fields) {
+            Ok(x) => x,
+            Err(err) => return Err(table_error(err)),
+        };
+        if let Some(header) = header.filter(|_| keep_header) {
+            window.lines.push(LineResult {
+                path: path.clone(),
+                line_number: header.line_number,
+                byte_offset: header.byte_offset,
+                text: header.text,
+                terminator: "\n".to_string(),
+                is_match: false,
+                submatches: Vec::new(),
+            });
+        }
+        for record in records {
+            let columns = match &columns {
+                Some(columns) => columns.clone(),
+                None => (0..record.fields.len()).collect(),
             };
-            if is_matched != config.options.contains(&GrepOption::Invert) {
-                while prev_unmatched_lines.len() > 0 {
-                    matches.push(prev_unmatched_lines.pop_front().expect("impossible"));
-                }
-                matches.push(line_result);
-                post_lines_left = config.num_post_lines;
-            } else {
-                if post_lines_left > 0 {
-                    matches.push(line_result);
-                    post_lines_left -= 1;
-                } else {
-                    prev_unmatched_lines.push_back(line_result);
-                    while prev_unmatched_lines.len() > config.num_pre_lines {
-                        prev_unmatched_lines.pop_front();
+            let is_matched = columns
+                .iter()
+                .any(|&column| match record.fields.get(column) {
+                    Some(field) => config.queries.iter().any(|query| query.is_match(field)),
+                    None => false,
+                });
+            let is_selected = is_matched != config.options.contains(&GrepOption::Invert);
+            let mut submatches = Vec::new();
+            if is_selected && is_matched {
+                for &column in &columns {
+                    let field = match record.fields.get(column) {
+                        Some(field) => field,
+                        None => continue,
+                    };
+                    let matches = find_matches(
+                        config.queries,
+                        field,
+                        config.replacement,
+                        config.options.contains(&GrepOption::PreserveCase),
+                    );
+                    // matches are left out of a field whose raw text can't be matched up
+                    for mut submatch in matches {
+                        if let Some((start, end)) =
+                            table::raw_span(&record, column, submatch.start, submatch.end)
+                        {
+                            submatch.start = start;
+                            submatch.end = end;
+                            submatches.push(submatch);
+                        }
                     }
                 }
+                submatches.sort_by_key(|submatch| submatch.start);
             }
-            // clear buffer for next line
+            window.push(LineResult {
+                path: path.clone(),
+                line_number: record.line_number,
+                byte_offset: record.byte_offset,
+                text: record.text,
+                terminator: "\n".to_string(),
+                is_match: is_selected,
+                submatches,
+            });
+        }
+        let file_result = FileResult {
+            file_path: config.filename.clone(),
+            members,
+            lines: window.lines,
+            bytes_searched: contents.len() as u64,
+            elapsed: start.elapsed(),
+        };
+        file_result.check_rep();
+        Ok(file_result)
+    }
+    /// Searches the values selected by a JSON path.
+    /// `.ndjson` and `.jsonl` inputs are read one record per line.
+    fn from_json<R: BufRead>(
+        config: &GrepSingleFileConfig,
+        members: Vec<String>,
+        mut reader: R,
+        selector: &Selector,
+    ) -> Result<FileResult, GrepError> {
+        let start = Instant::now();
+        let name = match members.last() {
+            Some(member) => member.to_lowercase(),
+            None => config.filename.to_string_lossy().to_lowercase(),
+        };
+        let is_stream = name.ends_with(".ndjson") || name.ends_with(".jsonl");
+        let file_path = member_path(config.filename, &members);
+        let mut window = ContextWindow::new(config.num_pre_lines, config.num_post_lines);
+        let mut bytes_searched = 0;
+        let mut buffer = String::new();
+        let mut line_number = 1;
+        loop {
             buffer.clear();
-            line_number += 1;
+            let num_bytes = if is_stream {
+                reader.read_line(&mut buffer)
+            } else {
+                reader.read_to_string(&mut buffer)
+            };
+            match num_bytes {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(err) => return Err(read_error(config.filename, err)),
+            }
+            bytes_searched += buffer.len() as u64;
+            if !buffer.trim().is_empty() {
+                let root = match json_path::parse(&buffer, line_number) {
+                    Ok(x) => x,
+                    Err(err) => return Err(GrepError::JsonPath(file_path, err)),
+                };
+                for (path, node) in selector.select(&root) {
+                    let value = node.to_text();
+                    let is_matched = config.queries.iter().any(|query| query.is_match(&value));
+                    let is_selected = is_matched != config.options.contains(&GrepOption::Invert);
+                    let prefix = format!("{}: ", path);
+                    let submatches = if is_selected && is_matched {
+                        let mut submatches = find_matches(
+                            config.queries,
+                            &value,
+                            config.replacement,
+                            config.options.contains(&GrepOption::PreserveCase),
+                        );
+                        for submatch in &mut submatches {
+                            submatch.start += prefix.len();
+                            submatch.end += prefix.len();
+                        }
+                        submatches
+                    } else {
+                        Vec::new()
+                    };
+                    window.push(LineResult {
+                        path: file_path.clone(),
+                        line_number: node.line_number,
+                        byte_offset: bytes_searched - buffer.len() as u64 + node.line_offset as u64,
+                        text: format!("{}{}", prefix, value),
+                        terminator: "\n".to_string(),
+                        is_match: is_selected,
+                        submatches,
+                    });
+                }
+            }
+            line_number += buffer.matches('\n').count() as i32;
         }
-
         let file_result = FileResult {
             file_path: config.filename.clone(),
-            lines: matches,
+            members,
+            lines: window.lines,
+            bytes_searched,
+            elapsed: start.elapsed(),
         };
         file_result.check_rep();
-        return Ok(file_result);
+        Ok(file_result)
     }
+    /// The `begin`, `match`/`context` and `end` messages of this file; its stats are added to `total`.
+    /// Like ripgrep, a file without any line to report has no messages but is still counted.
+    fn to_json_lines(&self, total: &mut json_output::Stats) -> Vec<String> {
+        let path = self.display_path();
+        let mut stats = json_output::Stats {
+            elapsed: self.elapsed,
+            searches: 1,
+            bytes_searched: self.bytes_searched,
+            ..Default::default()
+        };
+        if self.lines.is_empty() {
+            total.add(&stats);
+            return Vec::new();
+        }
+        let mut file_messages = vec![json_output::begin(&path)];
+        for line in &self.lines {
+            let text = format!("{}{}", line.text, line.terminator);
+            let submatches: Vec<(usize, usize, Option<&str>)> = line
+                .submatches
+                .iter()
+                .map(|submatch| {
+                    (
+                        submatch.start,
+                        submatch.end,
+                        submatch.replacement.as_deref(),
+                    )
+                })
+                .collect();
+            file_messages.push(json_output::line(&json_output::Line {
+                path: &path,
+                is_match: line.is_match,
+                text: &text,
+                line_number: line.line_number,
+                absolute_offset: line.byte_offset,
+                submatches: &submatches,
+            }));
+            if line.is_match {
+                stats.matched_lines += 1;
+                stats.matches += line.submatches.len() as u64;
+            }
+        }
+        if stats.matched_lines > 0 {
+            stats.searches_with_match = 1;
+        }
+        for message in &file_messages {
+            stats.bytes_printed += message.len() as u64 + 1;
+        }
+        file_messages.push(json_output::end(&path, &stats));
+        total.add(&stats);
+        file_messages
+    }
+    /// The path, with the archive members after `!` as in `logs.zip!app.log`.
+    pub fn display_path(&self) -> String {
+        member_path(&self.file_path, &self.members)
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// `path`, or for a member of an archive `archive!member`, as errors name it.
+fn member_path(path: &path::Path, members: &[String]) -> path::PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    for member in members {
+        name.push("!");
+        name.push(member);
+    }
+    path::PathBuf::from(name)
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> u64 {
+    // without inodes only truncation is noticed
+    0
 }
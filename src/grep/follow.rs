@@ -0,0 +1,159 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    path, thread,
+    time::Duration,
+};
+
+use super::{
+    compile_queries, file_id, never, read_error, GrepError, GrepSingleFileConfig, LineResult,
+    LineSearcher,
+};
+use crate::grep_config::{GrepConfig, GrepOption};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Like `tail -F`: prints the matching lines of every file, then keeps printing
+/// matching lines as they are appended until `should_stop` returns true.
+/// A file that is replaced (a new inode) or truncated is searched again from its start.
+/// A file that cannot be read goes to `on_error`, once until it can be read again, and
+/// is tried again at every poll while the other files are still followed.
+pub fn follow(
+    config: &GrepConfig,
+    out: &mut dyn Write,
+    should_stop: &dyn Fn() -> bool,
+    on_error: &mut dyn FnMut(GrepError),
+) -> Result<(), GrepError> {
+    let queries = compile_queries(config)?;
+    let single_file_configs: Vec<GrepSingleFileConfig> = config
+        .filenames
+        .iter()
+        .map(|filename| GrepSingleFileConfig::new(config, &queries, None, filename, &never))
+        .collect();
+    let mut files: Vec<FollowedFile> = single_file_configs.iter().map(FollowedFile::new).collect();
+    let is_with_name = files.len() > 1;
+    loop {
+        let mut has_output = false;
+        for file in &mut files {
+            let name = file.path.to_string_lossy();
+            let lines = match file.poll() {
+                Ok(lines) => {
+                    file.has_error = false;
+                    lines
+                }
+                Err(err) => {
+                    if !file.has_error {
+                        file.has_error = true;
+                        on_error(err);
+                    }
+                    continue;
+                }
+            };
+            for line in lines {
+                let lines = line.to_lines(
+                    if is_with_name { Some(&name) } else { None },
+                    config.options.contains(&GrepOption::LineNumber),
+                    config.options.contains(&GrepOption::OnlyMatching),
+                );
+                for line in lines {
+                    if let Err(err) = writeln!(out, "{}", line) {
+                        return Err(GrepError::Write(err.to_string()));
+                    }
+                    has_output = true;
+                }
+            }
+        }
+        if has_output {
+            if let Err(err) = out.flush() {
+                return Err(GrepError::Write(err.to_string()));
+            }
+        }
+        if should_stop() {
+            return Ok(());
+        }
+        if !has_output {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+struct FollowedFile<'a, 'b> {
+    config: &'b GrepSingleFileConfig<'a>,
+    path: &'a path::Path,
+    reader: Option<BufReader<File>>,
+    file_id: u64,
+    // bytes read from the current file, including `partial`
+    offset: u64,
+    // the last line, until its line ending arrives
+    partial: String,
+    searcher: LineSearcher<'a, 'b>,
+    // whether the last poll failed; its error was reported then
+    has_error: bool,
+}
+
+impl<'a, 'b> FollowedFile<'a, 'b> {
+    fn new(config: &'b GrepSingleFileConfig<'a>) -> FollowedFile<'a, 'b> {
+        FollowedFile {
+            config,
+            path: config.filename,
+            reader: None,
+            file_id: 0,
+            offset: 0,
+            partial: String::new(),
+            searcher: LineSearcher::new(config, config.filename.clone(), 1, 0),
+            has_error: false,
+        }
+    }
+    /// Reads whatever was appended since the last poll.
+    fn poll(&mut self) -> Result<Vec<LineResult>, GrepError> {
+        // finish the current file first, in case it was just rotated away
+        self.read_available()?;
+        let mut lines = self.take_lines();
+        let metadata = match fs::metadata(self.path) {
+            Ok(metadata) => metadata,
+            // rotated away and not recreated yet
+            Err(_err) if self.reader.is_some() => return Ok(lines),
+            Err(err) => return Err(GrepError::File(self.path.to_path_buf(), err)),
+        };
+        if self.reader.is_none()
+            || file_id(&metadata) != self.file_id
+            || metadata.len() < self.offset
+        {
+            let f = match File::open(self.path) {
+                Ok(f) => f,
+                Err(err) => return Err(GrepError::File(self.path.to_path_buf(), err)),
+            };
+            self.reader = Some(BufReader::new(f));
+            self.file_id = file_id(&metadata);
+            self.offset = 0;
+            self.partial.clear();
+            self.searcher = LineSearcher::new(self.config, self.config.filename.clone(), 1, 0);
+            self.read_available()?;
+            lines.append(&mut self.take_lines());
+        }
+        Ok(lines)
+    }
+    fn read_available(&mut self) -> Result<(), GrepError> {
+        let reader = match &mut self.reader {
+            Some(reader) => reader,
+            None => return Ok(()),
+        };
+        loop {
+            let num_bytes = match reader.read_line(&mut self.partial) {
+                Ok(num_bytes) => num_bytes,
+                Err(err) => return Err(read_error(self.path, err)),
+            };
+            if num_bytes == 0 {
+                return Ok(());
+            }
+            self.offset += num_bytes as u64;
+            if self.partial.ends_with('\n') {
+                self.searcher.push_line(&self.partial);
+                self.partial.clear();
+            }
+        }
+    }
+    fn take_lines(&mut self) -> Vec<LineResult> {
+        std::mem::take(&mut self.searcher.window.lines)
+    }
+}
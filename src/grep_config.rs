@@ -1,193 +1,839 @@
 use std::{
     collections::HashSet,
-    fs,
+    env, error, fmt, fs, io,
     path::{self, PathBuf},
+    time::SystemTime,
 };
 
-#[derive(PartialEq, Eq, Hash)]
+use super::{
+    checkpoint, file_filter::FileFilter, file_meta, file_type::Types, glob, json_path::Selector,
+};
+
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum GrepOption {
     CaseInsensitive, // i
     Word,            // w
     Invert,          // v
     LineNumber,      // n
     Count,           // c
+    SearchArchives,  // z
+    Json,            // --json
+    KeepHeader,      // --header
+    OnlyMatching,    // o
+    PreserveCase,    // --preserve-case
+    Follow,          // --follow
+    Index,           // --index
+    Debug,           // --debug
+    TypeList,        // --type-list
+    Quiet,           // q
+    NoMessages,      // s
+}
+
+#[derive(Clone)]
+pub enum TableFormat {
+    Csv,
+    Tsv,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileRuleKind {
+    Include,
+    Exclude,
+    ExcludeDir,
+}
+
+// a glob from --include, --exclude, --exclude-dir or --iglob
+#[derive(Clone)]
+pub struct FileRule {
+    pub kind: FileRuleKind,
+    pub glob: String,
+    pub is_case_insensitive: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum ConfigError {
     UnknownOption(String),
     MissingArg(String),
-    TooManyArgs,
     Filename(String),
     IllegalOptionValue(String),
+    // two options given together, where one would be ignored
+    Conflict(String, String),
+    // an error in the defaults, and where they came from: `path:line` or `MINIGREP_OPTS`
+    Defaults(String, Box<ConfigError>),
 }
 
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::UnknownOption(x) => write!(f, "Unknown option: {}", x),
+            ConfigError::MissingArg(x) => write!(f, "Missing arg: {}", x),
+            ConfigError::Filename(x) => write!(f, "File name error: {}", x),
+            ConfigError::IllegalOptionValue(x) => write!(f, "Illegal option value: {}", x),
+            ConfigError::Conflict(x, y) => write!(f, "{} cannot be used with {}", x, y),
+            ConfigError::Defaults(origin, err) => write!(f, "In {}: {}", origin, err),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::Defaults(_, err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct GrepConfig {
     pub options: HashSet<GrepOption>,
     pub queries: Vec<String>,
     pub filenames: Vec<PathBuf>,
+    // those of `filenames` found in directories and by globs rather than named directly;
+    // binary files among them are skipped instead of reported
+    pub found_filenames: HashSet<PathBuf>,
     pub num_pre_lines: usize,
     pub num_post_lines: usize,
+    pub archive_max_depth: usize,
+    pub archive_max_ratio: u64,
+    pub table_format: Option<TableFormat>,
+    // columns searched in table mode, by name or 1-based index
+    pub fields: Vec<String>,
+    // selector such as `.items[].name`; only the selected JSON values are searched
+    pub json_path: Option<String>,
+    // template for `--replace`; `$1` and `${name}` expand to capture groups
+    pub replacement: Option<String>,
+    // where `--state-file` keeps the position each file was searched up to
+    pub state_file: Option<PathBuf>,
+    // rules for the files found by globs and directory walks, in command line order
+    pub file_rules: Vec<FileRule>,
+    // with any, the files found must have one of these types (-t)
+    pub types: Vec<String>,
+    // types that are skipped (-T)
+    pub excluded_types: Vec<String>,
+    // type definitions from --type-add, such as `proto:*.proto`
+    pub type_additions: Vec<String>,
+    // files larger than this many bytes are skipped (--max-filesize)
+    pub max_filesize: Option<u64>,
+    // only files modified after this time are searched (--newer-than, --newer)
+    pub newer_than: Option<SystemTime>,
+    // only files modified before this time are searched (--older-than)
+    pub older_than: Option<SystemTime>,
+    // only files owned by this user id are searched (--owner)
+    pub owner: Option<u32>,
 }
 
-impl GrepConfig {
-    fn check_rep(&self) {
-        if self.queries.len() == 0 {
-            panic!("GrepConfig.queries.len() == 0");
-        }
-        if self.filenames.len() == 0 {
-            panic!("GrepConfig.filenames.len() == 0");
-        }
-    }
-    pub fn new(args: &Vec<String>) -> Result<GrepConfig, ConfigError> {
-        #[derive(PartialEq)]
-        enum State {
-            Start,
-            Option,
-            QueryString,
-            Filename,
-            Done,
-        }
-        let mut state = State::Start;
-        let mut arg_index = 1;
-        let mut config = GrepConfig {
+/// Short options and the long options they stand for.
+const SHORT_OPTIONS: &[(char, &str)] = &[
+    ('i', "ignore-case"),
+    ('w', "word-regexp"),
+    ('v', "invert-match"),
+    ('n', "line-number"),
+    ('c', "count"),
+    ('e', "regexp"),
+    ('r', "replace"),
+    ('o', "only-matching"),
+    ('z', "search-zip"),
+    ('A', "after-context"),
+    ('B', "before-context"),
+    ('C', "context"),
+    ('q', "quiet"),
+    ('s', "no-messages"),
+    ('t', "type"),
+    ('T', "type-not"),
+];
+
+/// Other spellings of long options.
+const LONG_ALIASES: &[(&str, &str)] = &[
+    ("word", "word-regexp"),
+    ("invert", "invert-match"),
+    ("silent", "quiet"),
+];
+
+/// Long options that take a value.
+const VALUED_OPTIONS: &[&str] = &[
+    "regexp",
+    "replace",
+    "after-context",
+    "before-context",
+    "context",
+    "archive-depth",
+    "archive-max-ratio",
+    "field",
+    "json-path",
+    "state-file",
+    "include",
+    "exclude",
+    "exclude-dir",
+    "iglob",
+    "type",
+    "type-not",
+    "type-add",
+    "max-filesize",
+    "newer-than",
+    "older-than",
+    "newer",
+    "owner",
+];
+
+impl Default for GrepConfig {
+    fn default() -> GrepConfig {
+        GrepConfig {
             options: HashSet::new(),
             queries: Vec::new(),
             filenames: Vec::new(),
+            found_filenames: HashSet::new(),
             num_pre_lines: 0,
             num_post_lines: 0,
-        };
+            archive_max_depth: 3,
+            archive_max_ratio: 100,
+            table_format: None,
+            fields: Vec::new(),
+            json_path: None,
+            replacement: None,
+            state_file: None,
+            file_rules: Vec::new(),
+            types: Vec::new(),
+            excluded_types: Vec::new(),
+            type_additions: Vec::new(),
+            max_filesize: None,
+            newer_than: None,
+            older_than: None,
+            owner: None,
+        }
+    }
+}
+
+impl GrepConfig {
+    /// Starts a config to be filled in with typed setters, as the command line is parsed.
+    pub fn builder() -> GrepConfigBuilder {
+        GrepConfigBuilder::default()
+    }
+    /// Parses `minigrep [options] query filename` the way GNU grep does: options may come
+    /// in any order and after the positional arguments, long options take their value
+    /// as `--context=3` or `--context 3`, short ones as `-C3` or `-C 3`, and `--` ends
+    /// the options. With `-e`, every positional argument is a filename.
+    pub fn new(args: &[String]) -> Result<GrepConfig, ConfigError> {
+        GrepConfigBuilder::from_args(args)?.build()
+    }
+    /// The file types for `-t` and `-T`, including those of `--type-add`, one per line.
+    pub fn type_list(&self) -> Result<Vec<String>, ConfigError> {
+        match Types::new(&self.type_additions) {
+            Ok(types) => Ok(types.lines()),
+            Err(addition) => Err(ConfigError::IllegalOptionValue(addition)),
+        }
+    }
+}
+
+/// Builds a `GrepConfig` from code rather than from command line arguments, such as
+/// `GrepConfig::builder().query("this").path("src").context(2).build()`.
+#[derive(Default)]
+pub struct GrepConfigBuilder {
+    // `filenames` is left empty until `build` looks up `paths`
+    config: GrepConfig,
+    // files, directories and globs, in the order given
+    paths: Vec<PathBuf>,
+    // the long names of the options parsed from arguments, so `serve` can vet them
+    option_names: Vec<String>,
+}
 
-        while state != State::Done {
-            if arg_index >= args.len() {
-                match state {
-                    State::Start => return Err(ConfigError::MissingArg("query".to_string())),
-                    State::Option => return Err(ConfigError::MissingArg("query".to_string())),
-                    State::QueryString => return Err(ConfigError::MissingArg("query".to_string())),
-                    State::Filename => return Err(ConfigError::MissingArg("filename".to_string())),
-                    State::Done => panic!("impossible"),
+impl GrepConfigBuilder {
+    /// Parses the arguments as `GrepConfig::new` does, leaving the paths to `build`.
+    pub(crate) fn from_args(args: &[String]) -> Result<GrepConfigBuilder, ConfigError> {
+        let mut builder = GrepConfig::builder();
+        let mut positionals = Vec::new();
+        let mut arg_index = 1;
+        while arg_index < args.len() {
+            if args[arg_index] == "--" {
+                positionals.extend_from_slice(&args[arg_index + 1..]);
+                break;
+            }
+            arg_index = match builder.parse_option(args, arg_index)? {
+                Some(next_index) => next_index,
+                None => {
+                    positionals.push(args[arg_index].clone());
+                    arg_index + 1
                 }
+            };
+        }
+        let mut positionals = positionals.into_iter();
+        // without -e the first positional argument is the query
+        if builder.config.queries.is_empty() {
+            if let Some(query) = positionals.next() {
+                builder.query(query);
             }
-            state = match state {
-                State::Start => State::Option,
-                State::Option => {
-                    let arg = &args[arg_index];
-                    if arg.as_bytes()[0] as char == '-' {
-                        for char_index in 1..arg.len() {
-                            let option = match arg.as_bytes()[char_index] as char {
-                                'i' => Some(GrepOption::CaseInsensitive),
-                                'w' => Some(GrepOption::Word),
-                                'v' => Some(GrepOption::Invert),
-                                'n' => Some(GrepOption::LineNumber),
-                                'c' => Some(GrepOption::Count),
-                                'e' => {
-                                    arg_index += 1;
-                                    config.queries.push(args[arg_index].clone());
-                                    None
-                                }
-                                c if c == 'A' || c == 'B' || c == 'C' => {
-                                    arg_index += 1;
-                                    let size: usize = match args[arg_index].parse() {
-                                        Ok(x) => x,
-                                        Err(_) => {
-                                            return Err(ConfigError::IllegalOptionValue(
-                                                args[arg_index].clone(),
-                                            ))
-                                        }
-                                    };
-                                    match c {
-                                        'A' => config.num_post_lines = size,
-                                        'B' => config.num_pre_lines = size,
-                                        'C' => {
-                                            config.num_pre_lines = size;
-                                            config.num_post_lines = size;
-                                        }
-                                        _ => panic!("impossible"),
-                                    }
-                                    None
-                                }
-                                other_char => {
-                                    return Err(ConfigError::UnknownOption(other_char.to_string()))
-                                }
-                            };
-                            if option.is_some() {
-                                config.options.insert(option.expect("impossible"));
-                            }
-                        }
-                        arg_index += 1;
-                        State::Option
-                    } else {
-                        State::QueryString
+        }
+        for operand in positionals {
+            builder.path(operand);
+        }
+        Ok(builder)
+    }
+    /// The long names of the options parsed by `from_args`, in the order given.
+    pub(crate) fn option_names(&self) -> &[String] {
+        &self.option_names
+    }
+    /// Adds a pattern; a line is selected when any of them matches.
+    pub fn query(&mut self, query: impl Into<String>) -> &mut Self {
+        self.config.queries.push(query.into());
+        self
+    }
+    /// Adds a file, a directory whose files are searched or a glob.
+    pub fn path(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.paths.push(path.into());
+        self
+    }
+    pub fn option(&mut self, option: GrepOption) -> &mut Self {
+        self.config.options.insert(option);
+        self
+    }
+    /// The number of lines shown before each selected line.
+    pub fn before_context(&mut self, num_lines: usize) -> &mut Self {
+        self.config.num_pre_lines = num_lines;
+        self
+    }
+    /// The number of lines shown after each selected line.
+    pub fn after_context(&mut self, num_lines: usize) -> &mut Self {
+        self.config.num_post_lines = num_lines;
+        self
+    }
+    /// The number of lines shown both before and after each selected line.
+    pub fn context(&mut self, num_lines: usize) -> &mut Self {
+        self.before_context(num_lines).after_context(num_lines)
+    }
+    pub fn archive_max_depth(&mut self, depth: usize) -> &mut Self {
+        self.config.archive_max_depth = depth;
+        self
+    }
+    pub fn archive_max_ratio(&mut self, ratio: u64) -> &mut Self {
+        self.config.archive_max_ratio = ratio;
+        self
+    }
+    pub fn table_format(&mut self, format: TableFormat) -> &mut Self {
+        self.config.table_format = Some(format);
+        self
+    }
+    /// Adds a column to search in table mode, by name or 1-based index.
+    pub fn field(&mut self, field: impl Into<String>) -> &mut Self {
+        self.config.fields.push(field.into());
+        self
+    }
+    pub fn json_path(&mut self, selector: impl Into<String>) -> &mut Self {
+        self.config.json_path = Some(selector.into());
+        self
+    }
+    pub fn replacement(&mut self, template: impl Into<String>) -> &mut Self {
+        self.config.replacement = Some(template.into());
+        self
+    }
+    pub fn state_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.config.state_file = Some(path.into());
+        self
+    }
+    /// Adds an include or exclude rule; the last one that matches a file decides.
+    pub fn file_rule(&mut self, rule: FileRule) -> &mut Self {
+        self.config.file_rules.push(rule);
+        self
+    }
+    pub fn file_type(&mut self, name: impl Into<String>) -> &mut Self {
+        self.config.types.push(name.into());
+        self
+    }
+    pub fn excluded_type(&mut self, name: impl Into<String>) -> &mut Self {
+        self.config.excluded_types.push(name.into());
+        self
+    }
+    /// Adds a type definition such as `proto:*.proto`.
+    pub fn type_addition(&mut self, definition: impl Into<String>) -> &mut Self {
+        self.config.type_additions.push(definition.into());
+        self
+    }
+    pub fn max_filesize(&mut self, num_bytes: u64) -> &mut Self {
+        self.config.max_filesize = Some(num_bytes);
+        self
+    }
+    pub fn newer_than(&mut self, time: SystemTime) -> &mut Self {
+        self.config.newer_than = Some(time);
+        self
+    }
+    pub fn older_than(&mut self, time: SystemTime) -> &mut Self {
+        self.config.older_than = Some(time);
+        self
+    }
+    pub fn owner(&mut self, uid: u32) -> &mut Self {
+        self.config.owner = Some(uid);
+        self
+    }
+    /// Checks the settings and looks up the files named by the paths.
+    pub fn build(&self) -> Result<GrepConfig, ConfigError> {
+        let mut config = self.config.clone();
+        let filter = match FileFilter::new(&config) {
+            Ok(x) => x,
+            Err(value) => return Err(ConfigError::IllegalOptionValue(value)),
+        };
+        // `--type-list` prints the types instead of searching
+        if config.options.contains(&GrepOption::TypeList) {
+            return Ok(config);
+        }
+        if config.queries.is_empty() {
+            return Err(ConfigError::MissingArg("query".to_string()));
+        }
+        if self.paths.is_empty() {
+            return Err(ConfigError::MissingArg("filename".to_string()));
+        }
+        if let Some(selector) = &config.json_path {
+            if Selector::new(selector).is_err() {
+                return Err(ConfigError::IllegalOptionValue(selector.clone()));
+            }
+        }
+        if !config.fields.is_empty() && config.table_format.is_none() {
+            return Err(ConfigError::IllegalOptionValue("--field".to_string()));
+        }
+        if config.options.contains(&GrepOption::KeepHeader) && config.table_format.is_none() {
+            return Err(ConfigError::IllegalOptionValue("--header".to_string()));
+        }
+        if config.options.contains(&GrepOption::PreserveCase) && config.replacement.is_none() {
+            return Err(ConfigError::IllegalOptionValue(
+                "--preserve-case".to_string(),
+            ));
+        }
+        if let Some((option, ignored)) = conflict(&config) {
+            return Err(ConfigError::Conflict(
+                option.to_string(),
+                ignored.to_string(),
+            ));
+        }
+        let mut seen = HashSet::new();
+        for path in &self.paths {
+            let mut found = Vec::new();
+            let is_found = if config.options.contains(&GrepOption::Index) && path.is_dir() {
+                // the candidate files are looked up in the index when searching
+                found.push(path.clone());
+                false
+            } else {
+                lookup_filenames(&mut found, path, &filter)?
+            };
+            // `a.txt`, `./a.txt` and `*.txt` may all name the same file, which is searched once
+            for path in found {
+                if seen.insert(checkpoint::key(&path)) {
+                    if is_found {
+                        config.found_filenames.insert(path.clone());
                     }
+                    config.filenames.push(path);
                 }
-                State::QueryString => {
-                    if arg_index < args.len() - 1 {
-                        // there is still place for filename
-                        config.queries.push(args[arg_index].clone());
-                        arg_index += 1;
-                    }
-                    State::Filename
+            }
+        }
+        Ok(config)
+    }
+    /// Applies the option at `args[arg_index]`, with its value when it takes one.
+    /// Returns the index of the argument after it, or `None` when it is not an option.
+    fn parse_option(
+        &mut self,
+        args: &[String],
+        arg_index: usize,
+    ) -> Result<Option<usize>, ConfigError> {
+        let arg = &args[arg_index];
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, attached) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let name = match LONG_ALIASES.iter().find(|(alias, _)| *alias == name) {
+                Some((_, name)) => name,
+                None => name,
+            };
+            if !VALUED_OPTIONS.contains(&name) {
+                if attached.is_some() {
+                    return Err(ConfigError::IllegalOptionValue(arg.clone()));
                 }
-                State::Filename => {
-                    let wildcard = &args[arg_index];
-                    lookup_filenames(&mut config.filenames, wildcard)?;
-                    arg_index += 1;
-                    if args.len() != arg_index {
-                        return Err(ConfigError::TooManyArgs);
-                    }
-                    State::Done
+                self.apply_option(name, "")?;
+                return Ok(Some(arg_index + 1));
+            }
+            return match attached {
+                Some(value) => {
+                    self.apply_option(name, value)?;
+                    Ok(Some(arg_index + 1))
+                }
+                None => {
+                    self.apply_option(name, value(args, arg_index + 1)?)?;
+                    Ok(Some(arg_index + 2))
                 }
-                State::Done => panic!("impossible"),
             };
         }
-        if config.queries.len() == 0 {
-            return Err(ConfigError::MissingArg("query".to_string()));
+        let cluster = match arg.strip_prefix('-') {
+            Some(cluster) if !cluster.is_empty() => cluster,
+            // `-` names standard input in other tools, so it is not taken for an option
+            _ => return Ok(None),
+        };
+        for (char_index, c) in cluster.char_indices() {
+            let name = match SHORT_OPTIONS.iter().find(|(short, _)| *short == c) {
+                Some((_, name)) => name,
+                None => return Err(ConfigError::UnknownOption(c.to_string())),
+            };
+            if VALUED_OPTIONS.contains(name) {
+                // the rest of the cluster is the value, as in `-C3` and `-efoo`
+                let rest = &cluster[char_index + c.len_utf8()..];
+                if !rest.is_empty() {
+                    self.apply_option(name, rest)?;
+                    return Ok(Some(arg_index + 1));
+                }
+                self.apply_option(name, value(args, arg_index + 1)?)?;
+                return Ok(Some(arg_index + 2));
+            }
+            self.apply_option(name, "")?;
         }
-        config.check_rep();
-        return Ok(config);
+        Ok(Some(arg_index + 1))
+    }
+    /// Applies the option with the long name `name`; `value` is empty for options without one.
+    fn apply_option(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        self.option_names.push(name.to_string());
+        if let Some(option) = flag(name) {
+            self.option(option);
+            return Ok(());
+        }
+        // `--no-ignore-case` and the like turn off a flag given earlier, as in the defaults
+        if let Some(option) = name.strip_prefix("no-").and_then(flag) {
+            self.config.options.remove(&option);
+            return Ok(());
+        }
+        self.apply_valued_option(name, value)
+    }
+    fn apply_valued_option(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        let number = || match value.parse::<usize>() {
+            Ok(x) => Ok(x),
+            Err(_) => Err(ConfigError::IllegalOptionValue(value.to_string())),
+        };
+        match name {
+            "regexp" => self.query(value),
+            "replace" => self.replacement(value),
+            "after-context" => self.after_context(number()?),
+            "before-context" => self.before_context(number()?),
+            "context" => self.context(number()?),
+            "archive-depth" => self.archive_max_depth(number()?),
+            "archive-max-ratio" => self.archive_max_ratio(number()? as u64),
+            "csv" => self.table_format(TableFormat::Csv),
+            "tsv" => self.table_format(TableFormat::Tsv),
+            "field" => self.field(value),
+            "json-path" => self.json_path(value),
+            "state-file" => self.state_file(value),
+            "include" | "exclude" | "exclude-dir" => self.file_rule(FileRule {
+                kind: match name {
+                    "include" => FileRuleKind::Include,
+                    "exclude" => FileRuleKind::Exclude,
+                    _ => FileRuleKind::ExcludeDir,
+                },
+                glob: value.to_string(),
+                is_case_insensitive: false,
+            }),
+            "max-filesize" => match file_meta::parse_size(value) {
+                Some(size) => self.max_filesize(size),
+                None => return Err(ConfigError::IllegalOptionValue(value.to_string())),
+            },
+            "newer-than" | "older-than" => {
+                let time = match file_meta::parse_time(value, SystemTime::now()) {
+                    Some(x) => x,
+                    None => return Err(ConfigError::IllegalOptionValue(value.to_string())),
+                };
+                match name {
+                    "newer-than" => self.newer_than(time),
+                    _ => self.older_than(time),
+                }
+            }
+            "newer" => match file_meta::modified(path::Path::new(value)) {
+                Some(time) => self.newer_than(time),
+                None => return Err(ConfigError::Filename(value.to_string())),
+            },
+            "owner" => match file_meta::parse_owner(value) {
+                Some(uid) => self.owner(uid),
+                None => return Err(ConfigError::IllegalOptionValue(value.to_string())),
+            },
+            "type" => self.file_type(value),
+            "type-not" => self.excluded_type(value),
+            "type-add" => self.type_addition(value),
+            // like ripgrep's, a leading `!` excludes instead
+            "iglob" => self.file_rule(match value.strip_prefix('!') {
+                Some(glob) => FileRule {
+                    kind: FileRuleKind::Exclude,
+                    glob: glob.to_string(),
+                    is_case_insensitive: true,
+                },
+                None => FileRule {
+                    kind: FileRuleKind::Include,
+                    glob: value.to_string(),
+                    is_case_insensitive: true,
+                },
+            }),
+            // seen by `default_args`
+            "no-config" => self,
+            _ => return Err(ConfigError::UnknownOption(format!("--{}", name))),
+        };
+        Ok(())
     }
 }
 
-fn lookup_filenames(
-    filenames: &mut Vec<path::PathBuf>,
-    wildcard: &String,
-) -> Result<(), ConfigError> {
-    let path = PathBuf::from(&wildcard);
-    if path.is_file() {
-        filenames.push(path);
-        return Ok(());
+/// The default options from the config file and then `MINIGREP_OPTS`, which go in front
+/// of the command line arguments so that those win. The config file is
+/// `$MINIGREP_CONFIG_PATH`, or else `$XDG_CONFIG_HOME/minigrep/config`; it holds options
+/// with their values, split into words as the shell does, and `#` comments. There are
+/// no defaults when `args` contains `--no-config`.
+pub fn default_args(args: &[String]) -> Result<Vec<String>, ConfigError> {
+    let mut defaults = Vec::new();
+    if args.iter().any(|arg| arg == "--no-config") {
+        return Ok(defaults);
     }
-
-    let filename = match path.file_name() {
-        Some(filename) => filename.to_str().unwrap(),
-        None => return Err(ConfigError::Filename(wildcard.clone())),
+    let (path, is_explicit) = match env::var_os("MINIGREP_CONFIG_PATH") {
+        Some(path) => (Some(PathBuf::from(path)), true),
+        None => {
+            let config_home = match env::var_os("XDG_CONFIG_HOME") {
+                Some(dir) => Some(PathBuf::from(dir)),
+                None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+            };
+            (config_home.map(|dir| dir.join("minigrep/config")), false)
+        }
     };
+    if let Some(path) = path {
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                for (index, line) in contents.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let origin = format!("{}:{}", path.to_string_lossy(), index + 1);
+                    push_defaults(&mut defaults, line, origin)?;
+                }
+            }
+            // only a config file that was asked for has to exist
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !is_explicit => (),
+            Err(err) => {
+                return Err(ConfigError::Defaults(
+                    path.to_string_lossy().to_string(),
+                    Box::new(ConfigError::Filename(err.to_string())),
+                ))
+            }
+        }
+    }
+    if let Ok(opts) = env::var("MINIGREP_OPTS") {
+        push_defaults(&mut defaults, &opts, "MINIGREP_OPTS".to_string())?;
+    }
+    Ok(defaults)
+}
 
-    if filename.contains("*") || filename.contains("?") {
-        let wild_match = wildmatch::WildMatch::new(filename);
-        let parent = match path.parent() {
-            Some(parent) => parent,
-            None => return Err(ConfigError::Filename(wildcard.clone())),
-        };
-        let paths = match fs::read_dir(parent) {
-            Ok(paths) => paths,
-            Err(_err) => return Err(ConfigError::Filename(wildcard.clone())),
+/// Checks that `text` holds whole options only, so a mistake is reported where it was made.
+fn push_defaults(
+    defaults: &mut Vec<String>,
+    text: &str,
+    origin: String,
+) -> Result<(), ConfigError> {
+    let mut args = vec![String::new()];
+    match split_words(text) {
+        Some(words) => args.extend(words),
+        None => {
+            let err = ConfigError::IllegalOptionValue(format!("unterminated quote: {}", text));
+            return Err(ConfigError::Defaults(origin, Box::new(err)));
+        }
+    }
+    let mut scratch = GrepConfig::builder();
+    let mut arg_index = 1;
+    while arg_index < args.len() {
+        arg_index = match scratch.parse_option(&args, arg_index) {
+            Ok(Some(next_index)) => next_index,
+            Ok(None) => {
+                let err = ConfigError::UnknownOption(args[arg_index].clone());
+                return Err(ConfigError::Defaults(origin, Box::new(err)));
+            }
+            Err(err) => return Err(ConfigError::Defaults(origin, Box::new(err))),
         };
-        for path in paths {
-            let path = match path {
-                Ok(path) => path.path(),
-                Err(_err) => return Err(ConfigError::Filename(wildcard.clone())),
-            };
-            let filename = match path.file_name() {
-                Some(filename) => filename.to_str().unwrap(),
-                None => continue,
-            };
-            if path.is_file() && wild_match.matches(filename) {
-                filenames.push(path);
+    }
+    defaults.extend(args.into_iter().skip(1));
+    Ok(())
+}
+
+/// Splits `text` into words at unquoted whitespace, as the shell does: single quotes keep
+/// everything up to the next one, double quotes everything but `\"` and `\\`, and a
+/// backslash outside quotes keeps the next character. `None` when a quote is left open.
+fn split_words(text: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // so that `""` is a word too
+    let mut is_in_word = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if is_in_word {
+                    words.push(std::mem::take(&mut word));
+                    is_in_word = false;
+                }
+                continue;
             }
+            '\'' => loop {
+                match chars.next()? {
+                    '\'' => break,
+                    c => word.push(c),
+                }
+            },
+            '"' => loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => match chars.next()? {
+                        c @ ('"' | '\\') => word.push(c),
+                        c => word.extend(['\\', c]),
+                    },
+                    c => word.push(c),
+                }
+            },
+            // a trailing backslash stays as it is
+            '\\' => word.push(chars.next().unwrap_or('\\')),
+            c => word.push(c),
         }
-        return Ok(());
+        is_in_word = true;
+    }
+    if is_in_word {
+        words.push(word);
     }
+    Some(words)
+}
+
+/// The option a flag without a value sets, by its long name.
+fn flag(name: &str) -> Option<GrepOption> {
+    let option = match name {
+        "ignore-case" => GrepOption::CaseInsensitive,
+        "word-regexp" => GrepOption::Word,
+        "invert-match" => GrepOption::Invert,
+        "line-number" => GrepOption::LineNumber,
+        "count" => GrepOption::Count,
+        "search-zip" => GrepOption::SearchArchives,
+        "json" => GrepOption::Json,
+        "header" => GrepOption::KeepHeader,
+        "only-matching" => GrepOption::OnlyMatching,
+        "preserve-case" => GrepOption::PreserveCase,
+        "follow" => GrepOption::Follow,
+        "index" => GrepOption::Index,
+        "debug" => GrepOption::Debug,
+        "type-list" => GrepOption::TypeList,
+        "quiet" => GrepOption::Quiet,
+        "no-messages" => GrepOption::NoMessages,
+        _ => return None,
+    };
+    Some(option)
+}
+
+/// The value of the option before `arg_index`, which is the argument at `arg_index`.
+fn value(args: &[String], arg_index: usize) -> Result<&str, ConfigError> {
+    match args.get(arg_index) {
+        Some(value) => Ok(value),
+        None => Err(ConfigError::MissingArg(args[arg_index - 1].clone())),
+    }
+}
+
+/// The first two options given together where the first would have the second ignored.
+fn conflict(config: &GrepConfig) -> Option<(&'static str, &'static str)> {
+    let has = |option| config.options.contains(&option);
+    let follow = has(GrepOption::Follow);
+    let json = has(GrepOption::Json);
+    let quiet = has(GrepOption::Quiet);
+    let count = has(GrepOption::Count);
+    let only_matching = has(GrepOption::OnlyMatching);
+    let archives = has(GrepOption::SearchArchives);
+    let is_csv = matches!(config.table_format, Some(TableFormat::Csv));
+    let is_tsv = matches!(config.table_format, Some(TableFormat::Tsv));
+    let is_json_path = config.json_path.is_some();
+    let is_state_file = config.state_file.is_some();
+    let is_replacing = config.replacement.is_some();
+    let has_types = !config.types.is_empty();
+    let has_excluded_types = !config.excluded_types.is_empty();
+    let has_max_filesize = config.max_filesize.is_some();
+    let has_newer_than = config.newer_than.is_some();
+    let has_older_than = config.older_than.is_some();
+    let (has_context, context) = match (config.num_pre_lines > 0, config.num_post_lines > 0) {
+        (true, true) => (true, "-C"),
+        (true, false) => (true, "-B"),
+        (false, true) => (true, "-A"),
+        (false, false) => (false, ""),
+    };
+    let conflicts = [
+        // follow mode prints plain lines of plain text files as they are appended
+        (follow && json, "--follow", "--json"),
+        (follow && is_csv, "--follow", "--csv"),
+        (follow && is_tsv, "--follow", "--tsv"),
+        (follow && is_json_path, "--follow", "--json-path"),
+        (follow && archives, "--follow", "-z"),
+        (follow && count, "--follow", "-c"),
+        (follow && quiet, "--follow", "-q"),
+        (follow && has(GrepOption::Index), "--follow", "--index"),
+        (follow && is_state_file, "--follow", "--state-file"),
+        (follow && has_types, "--follow", "-t"),
+        (follow && has_excluded_types, "--follow", "-T"),
+        (follow && has_max_filesize, "--follow", "--max-filesize"),
+        (follow && has_newer_than, "--follow", "--newer-than"),
+        (follow && has_older_than, "--follow", "--older-than"),
+        (follow && config.owner.is_some(), "--follow", "--owner"),
+        // --json reports every match of each selected line
+        (json && count, "--json", "-c"),
+        (json && only_matching, "--json", "-o"),
+        (json && quiet, "--json", "-q"),
+        // -q prints nothing
+        (quiet && count, "-q", "-c"),
+        (quiet && only_matching, "-q", "-o"),
+        (quiet && has(GrepOption::LineNumber), "-q", "-n"),
+        (quiet && is_replacing, "-q", "-r"),
+        (quiet && has_context, "-q", context),
+        // -c prints only how many lines are selected
+        (count && only_matching, "-c", "-o"),
+        (count && has(GrepOption::LineNumber), "-c", "-n"),
+        (count && is_replacing, "-c", "-r"),
+        (count && has_context, "-c", context),
+        // -o prints the matches of selected lines alone, and lines selected by -v have none
+        (only_matching && has(GrepOption::Invert), "-o", "-v"),
+        (only_matching && has_context, "-o", context),
+        // a file is searched as a table or as JSON, not both
+        (is_json_path && is_csv, "--json-path", "--csv"),
+        (is_json_path && is_tsv, "--json-path", "--tsv"),
+        // checkpoints are line positions in plain files, which JSON values, table records
+        // and archive members do not have
+        (is_state_file && is_json_path, "--state-file", "--json-path"),
+        (is_state_file && is_csv, "--state-file", "--csv"),
+        (is_state_file && is_tsv, "--state-file", "--tsv"),
+        (is_state_file && archives, "--state-file", "-z"),
+    ];
+    conflicts
+        .iter()
+        .find(|(is_given, _, _)| *is_given)
+        .map(|(_, option, ignored)| (*option, *ignored))
+}
 
-    return Err(ConfigError::Filename(wildcard.clone()));
+/// Adds the files named by `wildcard`: the file itself, the files that `filter` selects
+/// below a directory or those matching a glob. Files named directly are not filtered.
+/// Returns whether the files were found in a directory or by a glob.
+fn lookup_filenames(
+    filenames: &mut Vec<path::PathBuf>,
+    path: &path::Path,
+    filter: &FileFilter,
+) -> Result<bool, ConfigError> {
+    if path.is_file() {
+        filenames.push(path.to_path_buf());
+        return Ok(false);
+    }
+    if path.is_dir() {
+        filenames.append(&mut glob::walk_dir(path, filter));
+        return Ok(true);
+    }
+    let wildcard = path.to_string_lossy();
+    if glob::is_glob(&wildcard) {
+        match glob::expand(&wildcard, filter) {
+            Ok(Some(mut matched)) => {
+                filenames.append(&mut matched);
+                return Ok(true);
+            }
+            // like a shell without `nullglob`, the glob is kept as it is, so it is
+            // reported as a missing file while the other files are searched
+            Ok(None) => (),
+            Err(err) => return Err(ConfigError::Filename(err)),
+        }
+    } else if wildcard.is_empty() {
+        return Err(ConfigError::Filename(wildcard.to_string()));
+    }
+    // a path that cannot be read is reported when the search opens it
+    filenames.push(path.to_path_buf());
+    Ok(false)
 }
@@ -0,0 +1,518 @@
+// an on-disk trigram index that narrows which files of a tree a search has to open
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use regex::Regex;
+use regex_syntax::hir::{Class, Hir, HirKind};
+
+use super::atomic_file;
+use super::grep::GrepError;
+
+#[cfg(target_os = "linux")]
+mod watch;
+#[cfg(target_os = "linux")]
+pub use watch::watch;
+
+/// The index of a tree is kept in this file at the top of the tree.
+pub const INDEX_FILE_NAME: &str = ".minigrep-index";
+/// Changes made after the index was written are appended here until the next compaction.
+pub const JOURNAL_FILE_NAME: &str = ".minigrep-index.journal";
+
+const MAGIC: &[u8] = b"minigrep index 1\n";
+
+struct IndexedFile {
+    // relative to the indexed directory
+    path: PathBuf,
+    // modification time in nanoseconds since the epoch
+    mtime: u64,
+    size: u64,
+    // false for files that are not valid UTF-8; they have no trigrams
+    is_text: bool,
+}
+
+/// One update of the index, as kept in the journal.
+enum Change {
+    // adds a file, replacing the entry with the same path
+    Add(IndexedFile, Vec<u32>),
+    Remove(PathBuf),
+}
+
+struct Index {
+    // removed and replaced files keep their id until the next compaction
+    files: Vec<IndexedFile>,
+    // path to the id of the current entry for it
+    ids: HashMap<PathBuf, u32>,
+    // trigram to the ids of the files containing it, in ascending order
+    postings: BTreeMap<u32, Vec<u32>>,
+}
+
+/// Indexes every file under `dir` and writes the index to `dir/.minigrep-index`.
+/// Hidden files and directories are not indexed. Returns the number of files indexed.
+pub fn build(dir: &Path) -> Result<usize, GrepError> {
+    let mut paths = Vec::new();
+    if let Err(err) = walk(dir, Path::new(""), &mut paths) {
+        return Err(index_error(dir, err));
+    }
+    let mut index = Index::new();
+    for path in paths {
+        if let Some(change @ Change::Add(..)) = read_change(dir, &path, None) {
+            index.apply(change);
+        }
+    }
+    if let Err(err) = index.save(dir) {
+        return Err(index_error(dir, err));
+    }
+    Ok(index.ids.len())
+}
+
+/// The files under the indexed `dir` that may match one of `queries`, sorted by path.
+/// Files that changed since the index was updated, by mtime or size, and files the index
+/// does not know yet are always candidates.
+pub(crate) fn candidates(
+    dir: &Path,
+    queries: &[Regex],
+    is_case_insensitive: bool,
+    is_invert: bool,
+) -> Result<Vec<PathBuf>, GrepError> {
+    let index = match Index::load(dir) {
+        Ok(x) => x,
+        Err(err) => return Err(index_error(dir, err)),
+    };
+    // an inverted search selects lines without the trigrams, which any file can have
+    let hits = if is_invert {
+        None
+    } else {
+        index.hits(queries, is_case_insensitive)
+    };
+    let mut paths = Vec::new();
+    if let Err(err) = walk(dir, Path::new(""), &mut paths) {
+        return Err(index_error(dir, err));
+    }
+    let mut candidates = Vec::new();
+    for path in paths {
+        let full_path = dir.join(&path);
+        let is_candidate = match index.ids.get(&path) {
+            Some(&id) => {
+                let file = &index.files[id as usize];
+                let is_stale = match fs::metadata(&full_path) {
+                    Ok(metadata) => file_stamp(&metadata) != (file.mtime, file.size),
+                    Err(_err) => continue,
+                };
+                let is_hit = match &hits {
+                    Some(hits) => hits.contains(&id),
+                    None => file.is_text,
+                };
+                is_stale || is_hit
+            }
+            None => true,
+        };
+        if is_candidate {
+            candidates.push(full_path);
+        }
+    }
+    Ok(candidates)
+}
+
+/// Reads the file at `path`, relative to `dir`, into the change that brings its entry up to date.
+/// Returns `None` when `stamp`, the mtime and size of the current entry, is still the file's.
+fn read_change(dir: &Path, path: &Path, stamp: Option<(u64, u64)>) -> Option<Change> {
+    let full_path = dir.join(path);
+    let (mtime, size) = match fs::symlink_metadata(&full_path) {
+        Ok(metadata) if metadata.is_file() => file_stamp(&metadata),
+        _ => return Some(Change::Remove(path.to_path_buf())),
+    };
+    if stamp == Some((mtime, size)) {
+        return None;
+    }
+    let contents = match fs::read(&full_path) {
+        Ok(x) => x,
+        Err(_err) => return Some(Change::Remove(path.to_path_buf())),
+    };
+    let is_text = std::str::from_utf8(&contents).is_ok();
+    let trigrams = if is_text {
+        let mut trigrams: Vec<u32> = file_trigrams(&contents).into_iter().collect();
+        trigrams.sort();
+        trigrams
+    } else {
+        Vec::new()
+    };
+    let file = IndexedFile {
+        path: path.to_path_buf(),
+        mtime,
+        size,
+        is_text,
+    };
+    Some(Change::Add(file, trigrams))
+}
+
+impl Index {
+    fn new() -> Index {
+        Index {
+            files: Vec::new(),
+            ids: HashMap::new(),
+            postings: BTreeMap::new(),
+        }
+    }
+    /// The mtime and size recorded for `path`.
+    fn stamp(&self, path: &Path) -> Option<(u64, u64)> {
+        let file = &self.files[*self.ids.get(path)? as usize];
+        Some((file.mtime, file.size))
+    }
+    fn apply(&mut self, change: Change) {
+        match change {
+            Change::Add(file, trigrams) => {
+                let id = self.files.len() as u32;
+                for trigram in trigrams {
+                    self.postings.entry(trigram).or_default().push(id);
+                }
+                self.ids.insert(file.path.clone(), id);
+                self.files.push(file);
+            }
+            Change::Remove(path) => {
+                self.ids.remove(&path);
+            }
+        }
+    }
+    /// The ids of the files containing every required trigram of at least one query,
+    /// or `None` when some query requires no trigram and every file is a candidate.
+    fn hits(&self, queries: &[Regex], is_case_insensitive: bool) -> Option<HashSet<u32>> {
+        let mut hits = HashSet::new();
+        for query in queries {
+            let hir = match regex_syntax::ParserBuilder::new()
+                .case_insensitive(is_case_insensitive)
+                .build()
+                .parse(query.as_str())
+            {
+                Ok(x) => x,
+                Err(_err) => return None,
+            };
+            let trigrams = required_trigrams(&hir);
+            if trigrams.is_empty() {
+                return None;
+            }
+            let mut files: Option<HashSet<u32>> = None;
+            for trigram in trigrams {
+                let posting: HashSet<u32> = match self.postings.get(&trigram) {
+                    Some(ids) => ids.iter().copied().collect(),
+                    None => HashSet::new(),
+                };
+                files = Some(match files {
+                    Some(files) => files.intersection(&posting).copied().collect(),
+                    None => posting,
+                });
+            }
+            hits.extend(files.unwrap_or_default());
+        }
+        Some(hits)
+    }
+    /// Writes the current entries to `dir/.minigrep-index`, dropping removed ones,
+    /// and starts an empty journal.
+    fn save(&self, dir: &Path) -> io::Result<()> {
+        let mut live: Vec<u32> = self.ids.values().copied().collect();
+        live.sort();
+        // old id to new id
+        let renumbered: HashMap<u32, u32> = live
+            .iter()
+            .enumerate()
+            .map(|(new_id, &id)| (id, new_id as u32))
+            .collect();
+        let mut bytes = MAGIC.to_vec();
+        push_u32(&mut bytes, live.len() as u32);
+        for &id in &live {
+            let file = &self.files[id as usize];
+            push_path(&mut bytes, &file.path);
+            push_u64(&mut bytes, file.mtime);
+            push_u64(&mut bytes, file.size);
+            bytes.push(file.is_text as u8);
+        }
+        let postings: Vec<(u32, Vec<u32>)> = self
+            .postings
+            .iter()
+            .map(|(trigram, ids)| {
+                let ids = ids.iter().filter_map(|id| renumbered.get(id).copied());
+                (*trigram, ids.collect())
+            })
+            .filter(|(_, ids): &(u32, Vec<u32>)| !ids.is_empty())
+            .collect();
+        push_u32(&mut bytes, postings.len() as u32);
+        for (trigram, ids) in &postings {
+            push_u32(&mut bytes, *trigram);
+            push_u32(&mut bytes, ids.len() as u32);
+            for id in ids {
+                push_u32(&mut bytes, *id);
+            }
+        }
+        // readers never see a half-written index
+        atomic_file::write(&dir.join(INDEX_FILE_NAME), &bytes)?;
+        // replaying the old journal over the new index would change nothing, as changes
+        // replace whole entries, so a crash before this point loses nothing
+        match fs::remove_file(dir.join(JOURNAL_FILE_NAME)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+    /// Reads `dir/.minigrep-index` and replays the journal written after it.
+    fn load(dir: &Path) -> io::Result<Index> {
+        let bytes = fs::read(dir.join(INDEX_FILE_NAME))?;
+        let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "corrupt index");
+        let mut reader = match bytes.strip_prefix(MAGIC) {
+            Some(rest) => ByteReader { bytes: rest },
+            None => return Err(corrupt()),
+        };
+        let mut index = Index::new();
+        for id in 0..reader.u32().ok_or_else(corrupt)? {
+            let file = reader.indexed_file().ok_or_else(corrupt)?;
+            index.ids.insert(file.path.clone(), id);
+            index.files.push(file);
+        }
+        for _ in 0..reader.u32().ok_or_else(corrupt)? {
+            let trigram = reader.u32().ok_or_else(corrupt)?;
+            let len = reader.u32().ok_or_else(corrupt)?;
+            let mut ids = Vec::new();
+            for _ in 0..len {
+                ids.push(reader.u32().ok_or_else(corrupt)?);
+            }
+            index.postings.insert(trigram, ids);
+        }
+        let journal = match fs::read(dir.join(JOURNAL_FILE_NAME)) {
+            Ok(x) => x,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let mut reader = ByteReader { bytes: &journal };
+        // a change cut short by a crash is the end of the journal
+        while let Some(change) = reader.change() {
+            index.apply(change);
+        }
+        Ok(index)
+    }
+}
+
+/// Appends `change` to the journal of the index in `dir`.
+fn journal(dir: &Path, change: &Change) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    match change {
+        Change::Add(file, trigrams) => {
+            bytes.push(b'+');
+            push_path(&mut bytes, &file.path);
+            push_u64(&mut bytes, file.mtime);
+            push_u64(&mut bytes, file.size);
+            bytes.push(file.is_text as u8);
+            push_u32(&mut bytes, trigrams.len() as u32);
+            for trigram in trigrams {
+                push_u32(&mut bytes, *trigram);
+            }
+        }
+        Change::Remove(path) => {
+            bytes.push(b'-');
+            push_path(&mut bytes, path);
+        }
+    }
+    let mut f = fs::File::options()
+        .create(true)
+        .append(true)
+        .open(dir.join(JOURNAL_FILE_NAME))?;
+    f.write_all(&bytes)
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.take(4)?;
+        Some(u32::from_le_bytes(bytes.try_into().expect("4 bytes")))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        let bytes = self.take(8)?;
+        Some(u64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+    }
+    fn path(&mut self) -> Option<PathBuf> {
+        let len = self.u32()? as usize;
+        match std::str::from_utf8(self.take(len)?) {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => None,
+        }
+    }
+    fn indexed_file(&mut self) -> Option<IndexedFile> {
+        Some(IndexedFile {
+            path: self.path()?,
+            mtime: self.u64()?,
+            size: self.u64()?,
+            is_text: self.take(1)?[0] != 0,
+        })
+    }
+    fn change(&mut self) -> Option<Change> {
+        match self.take(1)?[0] {
+            b'+' => {
+                let file = self.indexed_file()?;
+                let mut trigrams = Vec::new();
+                for _ in 0..self.u32()? {
+                    trigrams.push(self.u32()?);
+                }
+                Some(Change::Add(file, trigrams))
+            }
+            b'-' => Some(Change::Remove(self.path()?)),
+            _ => None,
+        }
+    }
+}
+
+fn push_u32(bytes: &mut Vec<u8>, n: u32) {
+    bytes.extend_from_slice(&n.to_le_bytes());
+}
+
+fn push_u64(bytes: &mut Vec<u8>, n: u64) {
+    bytes.extend_from_slice(&n.to_le_bytes());
+}
+
+// paths that are not valid UTF-8 are stored lossily, so they never match an entry
+// and are always searched
+fn push_path(bytes: &mut Vec<u8>, path: &Path) {
+    let path = path.to_string_lossy();
+    push_u32(bytes, path.len() as u32);
+    bytes.extend_from_slice(path.as_bytes());
+}
+
+fn index_error(dir: &Path, err: io::Error) -> GrepError {
+    GrepError::Index(format!("{}: {}", dir.to_string_lossy(), err))
+}
+
+/// Collects the regular files under `dir`, relative to the top of the walk, in sorted order.
+/// Hidden entries, such as `.git` and the index itself, are skipped, and so are symlinks.
+fn walk(top: &Path, dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(top.join(dir))? {
+        entries.push(entry?);
+    }
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let path = dir.join(entry.file_name());
+        if file_type.is_dir() {
+            walk(top, &path, paths)?;
+        } else if file_type.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn file_stamp(metadata: &fs::Metadata) -> (u64, u64) {
+    let mtime = match metadata
+        .modified()
+        .map(|time| time.duration_since(UNIX_EPOCH))
+    {
+        Ok(Ok(since_epoch)) => since_epoch.as_nanos() as u64,
+        _ => 0,
+    };
+    (mtime, metadata.len())
+}
+
+fn trigram(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+/// The distinct trigrams of a file, ASCII-lowercased so case-insensitive searches can use them.
+/// Lines are searched one at a time, so trigrams spanning a line ending are left out.
+fn file_trigrams(contents: &[u8]) -> HashSet<u32> {
+    let mut trigrams = HashSet::new();
+    let lowercase = contents.to_ascii_lowercase();
+    for window in lowercase.windows(3) {
+        if !window.contains(&b'\n') {
+            trigrams.insert(trigram(window));
+        }
+    }
+    trigrams
+}
+
+/// The trigrams every match of `hir` contains: those of each run of literal text
+/// the regex cannot match without.
+fn required_trigrams(hir: &Hir) -> HashSet<u32> {
+    let mut trigrams = HashSet::new();
+    let mut run = Vec::new();
+    collect_trigrams(hir, &mut run, &mut trigrams);
+    end_run(&mut run, &mut trigrams);
+    trigrams
+}
+
+fn collect_trigrams(hir: &Hir, run: &mut Vec<u8>, trigrams: &mut HashSet<u32>) {
+    match hir.kind() {
+        HirKind::Literal(literal) => run.extend(literal.0.iter().map(u8::to_ascii_lowercase)),
+        HirKind::Class(class) => match class_byte(class) {
+            Some(b) => run.push(b),
+            None => end_run(run, trigrams),
+        },
+        // zero-width, so the text on both sides stays adjacent
+        HirKind::Look(_) => (),
+        HirKind::Capture(capture) => collect_trigrams(&capture.sub, run, trigrams),
+        HirKind::Concat(subs) => {
+            for sub in subs {
+                collect_trigrams(sub, run, trigrams);
+            }
+        }
+        HirKind::Repetition(repetition) if repetition.min >= 1 => {
+            end_run(run, trigrams);
+            collect_trigrams(&repetition.sub, run, trigrams);
+            end_run(run, trigrams);
+        }
+        // alternations and optional parts require nothing in particular
+        _ => end_run(run, trigrams),
+    }
+}
+
+fn end_run(run: &mut Vec<u8>, trigrams: &mut HashSet<u32>) {
+    for window in run.windows(3) {
+        trigrams.insert(trigram(window));
+    }
+    run.clear();
+}
+
+/// The lowercase byte a class stands for when it only holds ASCII case variants of one letter,
+/// as `(?i)a` does.
+fn class_byte(class: &Class) -> Option<u8> {
+    let bytes: Vec<(u32, u32)> = match class {
+        Class::Unicode(class) => class
+            .ranges()
+            .iter()
+            .map(|range| (range.start() as u32, range.end() as u32))
+            .collect(),
+        Class::Bytes(class) => class
+            .ranges()
+            .iter()
+            .map(|range| (range.start() as u32, range.end() as u32))
+            .collect(),
+    };
+    let mut byte = None;
+    for (start, end) in bytes {
+        for c in start..=end {
+            let lower = match u8::try_from(c) {
+                Ok(b) if b.is_ascii() => b.to_ascii_lowercase(),
+                _ => return None,
+            };
+            if byte.is_some_and(|byte| byte != lower) {
+                return None;
+            }
+            byte = Some(lower);
+        }
+    }
+    byte
+}
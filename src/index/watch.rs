@@ -0,0 +1,215 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+
+use super::{index_error, journal, read_change, walk, Change, Index};
+use crate::grep::GrepError;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// the journal is folded into the index after this many changes
+const COMPACT_AFTER: usize = 1000;
+
+/// Keeps the index of `dir` up to date until `should_stop` returns true.
+/// Every change is appended to the journal as soon as inotify reports it, so searches
+/// never need a rebuild; the journal is compacted into the index at start and then
+/// every thousand changes. Changes made while nothing watched are picked up at start.
+pub fn watch(dir: &Path, should_stop: &dyn Fn() -> bool) -> Result<(), GrepError> {
+    let index = match Index::load(dir) {
+        Ok(x) => x,
+        Err(err) => return Err(index_error(dir, err)),
+    };
+    let inotify = match Inotify::init() {
+        Ok(x) => x,
+        Err(err) => return Err(index_error(dir, err)),
+    };
+    let mut watcher = Watcher {
+        dir,
+        inotify,
+        dirs: HashMap::new(),
+        index,
+        num_changes: 0,
+    };
+    // the watches come first, so nothing changed during the catch-up goes unnoticed
+    watcher.sync(Path::new(""))?;
+    watcher.compact()?;
+    let mut buffer = [0; 4096];
+    loop {
+        let mut dirty = BTreeSet::new();
+        loop {
+            let events = match watcher.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(index_error(dir, err)),
+            };
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    // events were lost, so the whole tree is compared with the index
+                    dirty.insert(PathBuf::new());
+                    continue;
+                }
+                if event.mask.contains(EventMask::IGNORED) {
+                    watcher.dirs.remove(&event.wd);
+                    continue;
+                }
+                let path = match (watcher.dirs.get(&event.wd), event.name) {
+                    (Some(parent), Some(name)) => parent.join(name),
+                    _ => continue,
+                };
+                if !is_hidden(&path) {
+                    dirty.insert(path);
+                }
+            }
+        }
+        for path in &dirty {
+            watcher.sync(path)?;
+        }
+        if watcher.num_changes >= COMPACT_AFTER {
+            watcher.compact()?;
+        }
+        if should_stop() {
+            return Ok(());
+        }
+        if dirty.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+struct Watcher<'a> {
+    dir: &'a Path,
+    inotify: Inotify,
+    // every watched directory, relative to `dir`
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    index: Index,
+    // changes journaled since the last compaction
+    num_changes: usize,
+}
+
+impl Watcher<'_> {
+    /// Brings the entries for `path`, a file or directory that changed, was added,
+    /// moved or removed, in line with what is on disk.
+    fn sync(&mut self, path: &Path) -> Result<(), GrepError> {
+        let (is_dir, is_file) = match fs::symlink_metadata(self.dir.join(path)) {
+            Ok(metadata) => (metadata.is_dir(), metadata.is_file()),
+            Err(_err) => (false, false),
+        };
+        let mut paths = Vec::new();
+        if is_dir {
+            self.watch_tree(path)?;
+            if let Err(err) = walk(self.dir, path, &mut paths) {
+                return Err(index_error(self.dir, err));
+            }
+        } else {
+            self.unwatch_tree(path);
+            paths.push(path.to_path_buf());
+        }
+        if !is_file {
+            // files of a directory that was removed or moved away, or that went missing
+            // while events were lost
+            let walked: HashSet<&PathBuf> = paths.iter().collect();
+            let gone: Vec<PathBuf> = self
+                .index
+                .ids
+                .keys()
+                .filter(|indexed| indexed.starts_with(path) && !walked.contains(indexed))
+                .cloned()
+                .collect();
+            for indexed in gone {
+                self.record(Change::Remove(indexed))?;
+            }
+        }
+        for path in paths {
+            if let Some(change) = read_change(self.dir, &path, self.index.stamp(&path)) {
+                self.record(change)?;
+            }
+        }
+        Ok(())
+    }
+    fn record(&mut self, change: Change) -> Result<(), GrepError> {
+        if let Change::Remove(path) = &change {
+            if !self.index.ids.contains_key(path) {
+                return Ok(());
+            }
+        }
+        if let Err(err) = journal(self.dir, &change) {
+            return Err(index_error(self.dir, err));
+        }
+        self.index.apply(change);
+        self.num_changes += 1;
+        Ok(())
+    }
+    /// Watches the directory `path` and every directory below it.
+    fn watch_tree(&mut self, path: &Path) -> Result<(), GrepError> {
+        let mask = WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MODIFY
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::ATTRIB
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO
+            | WatchMask::ONLYDIR
+            | WatchMask::DONT_FOLLOW;
+        let full_path = self.dir.join(path);
+        // a directory that is watched already keeps its descriptor, so a moved
+        // directory is only renamed here
+        let wd = match self.inotify.watches().add(&full_path, mask) {
+            Ok(wd) => wd,
+            // removed again before it could be watched
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(index_error(&full_path, err)),
+        };
+        self.dirs.insert(wd, path.to_path_buf());
+        let entries = match fs::read_dir(&full_path) {
+            Ok(x) => x,
+            Err(_err) => return Ok(()),
+        };
+        for entry in entries.flatten() {
+            let path = path.join(entry.file_name());
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if is_dir && !is_hidden(&path) {
+                self.watch_tree(&path)?;
+            }
+        }
+        Ok(())
+    }
+    /// Stops watching `path` and the directories below it, which were removed or moved away.
+    fn unwatch_tree(&mut self, path: &Path) {
+        let wds: Vec<WatchDescriptor> = self
+            .dirs
+            .iter()
+            .filter(|(_, dir)| dir.starts_with(path))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+        for wd in wds {
+            // fails for deleted directories, whose watches are gone already
+            let _ = self.inotify.watches().remove(wd.clone());
+            self.dirs.remove(&wd);
+        }
+    }
+    /// Rewrites the index without removed entries and empties the journal.
+    fn compact(&mut self) -> Result<(), GrepError> {
+        let result = self
+            .index
+            .save(self.dir)
+            .and_then(|()| Index::load(self.dir));
+        self.index = match result {
+            Ok(x) => x,
+            Err(err) => return Err(index_error(self.dir, err)),
+        };
+        self.num_changes = 0;
+        Ok(())
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().starts_with('.'),
+        None => false,
+    }
+}
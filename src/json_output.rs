@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+// messages follow ripgrep's `--json` schema
+
+#[derive(Default, Clone)]
+pub struct Stats {
+    pub elapsed: Duration,
+    pub searches: u64,
+    pub searches_with_match: u64,
+    pub bytes_searched: u64,
+    pub bytes_printed: u64,
+    pub matched_lines: u64,
+    pub matches: u64,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.elapsed += other.elapsed;
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.bytes_searched += other.bytes_searched;
+        self.bytes_printed += other.bytes_printed;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+    fn to_value(&self) -> Value {
+        json!({
+            "elapsed": duration(self.elapsed),
+            "searches": self.searches,
+            "searches_with_match": self.searches_with_match,
+            "bytes_searched": self.bytes_searched,
+            "bytes_printed": self.bytes_printed,
+            "matched_lines": self.matched_lines,
+            "matches": self.matches,
+        })
+    }
+}
+
+pub struct Line<'a> {
+    pub path: &'a str,
+    pub is_match: bool,
+    /// the line including its terminator
+    pub text: &'a str,
+    pub line_number: i32,
+    pub absolute_offset: u64,
+    /// byte ranges of the matches within `text`, with their replacements
+    pub submatches: &'a [(usize, usize, Option<&'a str>)],
+}
+
+pub fn begin(path: &str) -> String {
+    json!({
+        "type": "begin",
+        "data": { "path": { "text": path } },
+    })
+    .to_string()
+}
+
+pub fn line(line: &Line) -> String {
+    let submatches: Vec<Value> = line
+        .submatches
+        .iter()
+        .map(|&(start, end, replacement)| {
+            let mut submatch = json!({
+                "match": { "text": &line.text[start..end] },
+                "start": start,
+                "end": end,
+            });
+            if let Some(replacement) = replacement {
+                submatch["replacement"] = json!({ "text": replacement });
+            }
+            submatch
+        })
+        .collect();
+    json!({
+        "type": if line.is_match { "match" } else { "context" },
+        "data": {
+            "path": { "text": line.path },
+            "lines": { "text": line.text },
+            "line_number": line.line_number,
+            "absolute_offset": line.absolute_offset,
+            "submatches": submatches,
+        },
+    })
+    .to_string()
+}
+
+pub fn end(path: &str, stats: &Stats) -> String {
+    json!({
+        "type": "end",
+        "data": {
+            "path": { "text": path },
+            "binary_offset": null,
+            "stats": stats.to_value(),
+        },
+    })
+    .to_string()
+}
+
+/// A file that could not be searched; ripgrep only reports these on stderr.
+pub fn error(message: &str) -> String {
+    json!({
+        "type": "error",
+        "data": { "message": message },
+    })
+    .to_string()
+}
+
+pub fn summary(elapsed_total: Duration, stats: &Stats) -> String {
+    json!({
+        "type": "summary",
+        "data": {
+            "elapsed_total": duration(elapsed_total),
+            "stats": stats.to_value(),
+        },
+    })
+    .to_string()
+}
+
+fn duration(d: Duration) -> Value {
+    json!({
+        "secs": d.as_secs(),
+        "nanos": d.subsec_nanos(),
+        "human": format!("{:.6}s", d.as_secs_f64()),
+    })
+}
@@ -0,0 +1,397 @@
+// a small JSON parser that remembers the line of every value, and jq-style path selectors
+
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+}
+
+pub struct Node {
+    pub line_number: i32,
+    // offset of the first byte of that line within the parsed text
+    pub line_offset: usize,
+    pub value: Value,
+}
+
+impl Node {
+    /// The text queries are matched against: strings unquoted, everything else as compact JSON.
+    pub fn to_text(&self) -> String {
+        match &self.value {
+            Value::String(s) => s.clone(),
+            _ => self.to_json(),
+        }
+    }
+    fn to_json(&self) -> String {
+        match &self.value {
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => n.clone(),
+            Value::String(s) => serde_json::Value::String(s.clone()).to_string(),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_json()).collect();
+                format!("[{}]", items.join(","))
+            }
+            Value::Object(members) => {
+                let members: Vec<String> = members
+                    .iter()
+                    .map(|(key, value)| {
+                        format!(
+                            "{}:{}",
+                            serde_json::Value::String(key.clone()),
+                            value.to_json()
+                        )
+                    })
+                    .collect();
+                format!("{{{}}}", members.join(","))
+            }
+        }
+    }
+}
+
+// arrays and objects nested deeper than this are an error rather than a stack overflow;
+// the same limit as serde_json's
+const MAX_DEPTH: usize = 128;
+
+/// Parses one JSON document; `first_line` is the line number the text starts on.
+pub fn parse(text: &str, first_line: i32) -> Result<Node, String> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        line_number: first_line,
+        line_offset: 0,
+        depth: 0,
+    };
+    let node = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(node)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    line_number: i32,
+    // where the current line starts
+    line_offset: usize,
+    // arrays and objects open around the current position
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line_number, message)
+    }
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+    fn skip_whitespace(&mut self) {
+        while let Some(b) = self.peek() {
+            match b {
+                b'\n' => {
+                    self.line_number += 1;
+                    self.line_offset = self.pos + 1;
+                }
+                b' ' | b'\t' | b'\r' => (),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+    }
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected `{}`", expected as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+    fn parse_value(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        let line_number = self.line_number;
+        let line_offset = self.line_offset;
+        let value = match self.peek() {
+            Some(b'{') => self.nested(Self::parse_object)?,
+            Some(b'[') => self.nested(Self::parse_array)?,
+            Some(b'"') => Value::String(self.parse_string()?),
+            Some(b't') => self.parse_keyword("true", Value::Bool(true))?,
+            Some(b'f') => self.parse_keyword("false", Value::Bool(false))?,
+            Some(b'n') => self.parse_keyword("null", Value::Null)?,
+            Some(b) if b == b'-' || b.is_ascii_digit() => self.parse_number()?,
+            Some(_) => return Err(self.error("unexpected character")),
+            None => return Err(self.error("unexpected end of input")),
+        };
+        Ok(Node {
+            line_number,
+            line_offset,
+            value,
+        })
+    }
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let value = parse(self)?;
+        self.depth -= 1;
+        Ok(value)
+    }
+    fn parse_keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        if !self.text[self.pos..].starts_with(keyword) {
+            return Err(self.error("unexpected character"));
+        }
+        self.pos += keyword.len();
+        Ok(value)
+    }
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            if b.is_ascii_digit() || b == b'-' || b == b'+' || b == b'.' || b == b'e' || b == b'E' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let number = &self.text[start..self.pos];
+        if number.parse::<f64>().is_err() {
+            return Err(self.error(&format!("invalid number `{}`", number)));
+        }
+        Ok(Value::Number(number.to_string()))
+    }
+    fn parse_string(&mut self) -> Result<String, String> {
+        // the opening quote
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let end = match rest.find(['"', '\\', '\n']) {
+                Some(end) => end,
+                None => return Err(self.error("unterminated string")),
+            };
+            s.push_str(&rest[..end]);
+            self.pos += end;
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    s.push(self.parse_escape()?);
+                }
+                _ => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let high = self.parse_hex4()?;
+                if !(0xD800..0xDC00).contains(&high) {
+                    return char::from_u32(high).ok_or_else(|| self.error("invalid escape"));
+                }
+                // a surrogate pair
+                if !self.text[self.pos..].starts_with("\\u") {
+                    return Err(self.error("invalid escape"));
+                }
+                self.pos += 2;
+                let low = self.parse_hex4()?;
+                let code = 0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                return char::from_u32(code).ok_or_else(|| self.error("invalid escape"));
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let digits = match self.text.get(self.pos..self.pos + 4) {
+            Some(digits) => digits,
+            None => return Err(self.error("invalid escape")),
+        };
+        let code = match u32::from_str_radix(digits, 16) {
+            Ok(code) => code,
+            Err(_) => return Err(self.error("invalid escape")),
+        };
+        self.pos += 4;
+        Ok(code)
+    }
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            members.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+}
+
+enum Step {
+    Key(String),
+    Index(usize),
+    // `[]`, every element of an array or every value of an object
+    Each,
+}
+
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+impl Selector {
+    /// Parses selectors such as `.`, `.items[].name`, `.rows[0]` and `.["a key"]`.
+    pub fn new(selector: &str) -> Result<Selector, String> {
+        let invalid = || format!("invalid selector: {}", selector);
+        let mut steps = Vec::new();
+        let mut rest = selector;
+        if !rest.starts_with('.') && !rest.starts_with('[') {
+            return Err(invalid());
+        }
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let end = after_dot
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(after_dot.len());
+                if end > 0 {
+                    steps.push(Step::Key(after_dot[..end].to_string()));
+                } else if !(after_dot.is_empty() || after_dot.starts_with('[')) {
+                    return Err(invalid());
+                }
+                rest = &after_dot[end..];
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let end = match after_bracket.find(']') {
+                    Some(end) => end,
+                    None => return Err(invalid()),
+                };
+                let inner = &after_bracket[..end];
+                if inner.is_empty() {
+                    steps.push(Step::Each);
+                } else if inner.starts_with('"') {
+                    match serde_json::from_str::<String>(inner) {
+                        Ok(key) => steps.push(Step::Key(key)),
+                        Err(_) => return Err(invalid()),
+                    }
+                } else {
+                    match inner.parse() {
+                        Ok(index) => steps.push(Step::Index(index)),
+                        Err(_) => return Err(invalid()),
+                    }
+                }
+                rest = &after_bracket[end + 1..];
+            } else {
+                return Err(invalid());
+            }
+        }
+        Ok(Selector { steps })
+    }
+    /// Returns every selected value together with its concrete path, in document order.
+    pub fn select<'a>(&self, root: &'a Node) -> Vec<(String, &'a Node)> {
+        let mut selected = Vec::new();
+        select_steps(&self.steps, root, String::new(), &mut selected);
+        selected
+    }
+}
+
+fn select_steps<'a>(
+    steps: &[Step],
+    node: &'a Node,
+    path: String,
+    selected: &mut Vec<(String, &'a Node)>,
+) {
+    let (step, rest) = match steps.split_first() {
+        Some(x) => x,
+        None => {
+            let path = if path.is_empty() { ".".to_string() } else { path };
+            selected.push((path, node));
+            return;
+        }
+    };
+    match (step, &node.value) {
+        (Step::Key(key), Value::Object(members)) => {
+            for (name, value) in members {
+                if name == key {
+                    select_steps(rest, value, format!("{}{}", path, key_path(name)), selected);
+                }
+            }
+        }
+        (Step::Index(index), Value::Array(items)) => {
+            if let Some(item) = items.get(*index) {
+                select_steps(rest, item, format!("{}[{}]", path, index), selected);
+            }
+        }
+        (Step::Each, Value::Array(items)) => {
+            for (index, item) in items.iter().enumerate() {
+                select_steps(rest, item, format!("{}[{}]", path, index), selected);
+            }
+        }
+        (Step::Each, Value::Object(members)) => {
+            for (name, value) in members {
+                select_steps(rest, value, format!("{}{}", path, key_path(name)), selected);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn key_path(key: &str) -> String {
+    let is_identifier = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if is_identifier {
+        format!(".{}", key)
+    } else {
+        format!("[{}]", serde_json::Value::String(key.to_string()))
+    }
+}
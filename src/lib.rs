@@ -1,2 +1,17 @@
+mod archive;
+mod atomic_file;
+mod case;
+mod checkpoint;
+mod diff;
+mod file_filter;
+mod file_meta;
+mod file_type;
+mod glob;
 pub mod grep;
 pub mod grep_config;
+pub mod index;
+mod json_output;
+mod json_path;
+pub mod replace;
+pub mod serve;
+mod table;
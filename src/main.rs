@@ -1,51 +1,335 @@
-use std::env;
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use minigrep::{
     grep::{self, GrepError},
-    grep_config::{self, ConfigError},
+    grep_config::{self, ConfigError, GrepOption},
+    index,
+    replace::{self, ReplaceConfig},
+    serve,
 };
 
-fn main() {
+// exit statuses, as grep's: 0 when a line was selected, 1 when none was, 2 on errors
+const NO_MATCH: u8 = 1;
+const TROUBLE: u8 = 2;
+
+// where `replace --interactive` reads its answers when stdin is a terminal
+#[cfg(windows)]
+const TERMINAL: &str = "CONIN$";
+#[cfg(not(windows))]
+const TERMINAL: &str = "/dev/tty";
+
+fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
 
-    let config = match grep_config::GrepConfig::new(&args) {
+    if args.len() > 1 && args[1] == "replace" {
+        return run_replace(&args);
+    }
+    if args.len() > 1 && args[1] == "index" {
+        return run_index(&args);
+    }
+    if args.len() > 1 && args[1] == "serve" {
+        return run_serve(&args);
+    }
+
+    let config = match with_defaults(&args, 1).and_then(|args| grep_config::GrepConfig::new(&args))
+    {
         Ok(config) => config,
         Err(err) => {
-            match err {
-                ConfigError::UnknownOption(x) => eprintln!("Unknown option: {}", x),
-                ConfigError::MissingArg(x) => eprintln!("Missing arg: {}", x),
-                ConfigError::TooManyArgs => eprintln!("Too many args"),
-                ConfigError::Filename(x) => eprintln!("File name error: {}", x),
-                ConfigError::IllegalOptionValue(x) => eprintln!("Illegal option value: {}", x),
+            eprintln!("{}", err);
+            print_usage();
+            return ExitCode::from(TROUBLE);
+        }
+    };
+
+    if config.options.contains(&GrepOption::TypeList) {
+        return match config.type_list() {
+            Ok(lines) => {
+                lines.iter().for_each(|line| println!("{}", line));
+                ExitCode::SUCCESS
             }
-            eprintln!("\
-usage: minigrep [options] [query_string] filename
-  options:
-    -i           ignore case distinctions in both the query string and the file contents
-    -w           match only whole words
-    -v           select non-matching lines
-    -n           print line numbers with output lines
-    -c           print only a count of matching lines
-    -e pattern   set the query string
-    -A N         print N lines of leading context before matching lines
-    -B N         print N lines of trailing context after matching lines
-    -C N         print N lines of context surrounding matching lines");
-            return;
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::from(TROUBLE)
+            }
+        };
+    }
+
+    // files that cannot be searched are reported, with -s only by the exit status
+    let is_silent = config.options.contains(&GrepOption::NoMessages);
+    let mut has_file_errors = false;
+    let mut on_file_error = |err: &GrepError| {
+        has_file_errors = true;
+        if !is_silent {
+            eprintln!("{}", err);
         }
     };
 
+    if config.options.contains(&GrepOption::Quiet) {
+        return match grep::is_match(&config, &mut |err| on_file_error(&err)) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) if has_file_errors => ExitCode::from(TROUBLE),
+            Ok(false) => ExitCode::from(NO_MATCH),
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::from(TROUBLE)
+            }
+        };
+    }
+
+    if config.options.contains(&GrepOption::Follow) {
+        let mut on_error = |err| on_file_error(&err);
+        if let Err(err) = grep::follow(&config, &mut io::stdout(), &|| false, &mut on_error) {
+            eprintln!("{}", err);
+            return ExitCode::from(TROUBLE);
+        }
+        return ExitCode::SUCCESS;
+    }
+
     let grep_result = match grep::GrepResult::new(&config) {
         Ok(x) => x,
         Err(err) => {
-            match err {
-                GrepError::FileNotExists => eprintln!("File not exists"),
-                GrepError::NotValidUTF8 => eprintln!("File contents not being valid utf-8"),
-                GrepError::Query(x) => eprintln!("Query error: {}", x),
-            }
-            return;
+            eprintln!("{}", err);
+            return ExitCode::from(TROUBLE);
         }
     };
 
+    grep_result.errors().iter().for_each(&mut on_file_error);
     let s = grep_result.to_string(&config);
     println!("{}", s);
+    match (has_file_errors, grep_result.has_match()) {
+        (true, _) => ExitCode::from(TROUBLE),
+        (false, true) => ExitCode::SUCCESS,
+        (false, false) => ExitCode::from(NO_MATCH),
+    }
+}
+
+/// `args` with the default options inserted at `position`, in front of the given options.
+fn with_defaults(args: &[String], position: usize) -> Result<Vec<String>, ConfigError> {
+    let mut args = args.to_vec();
+    let defaults = grep_config::default_args(&args)?;
+    args.splice(position..position, defaults);
+    Ok(args)
+}
+
+fn run_replace(args: &[String]) -> ExitCode {
+    let config = match with_defaults(args, 2).and_then(|args| ReplaceConfig::new(&args)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            print_usage();
+            return ExitCode::from(TROUBLE);
+        }
+    };
+    // answers come from the terminal, or from stdin when they are piped in by a script
+    let mut input: Box<dyn io::BufRead> = if !config.interactive {
+        Box::new(io::empty())
+    } else if !io::stdin().is_terminal() {
+        Box::new(io::stdin().lock())
+    } else {
+        match fs::File::open(TERMINAL) {
+            Ok(terminal) => Box::new(io::BufReader::new(terminal)),
+            Err(err) => {
+                eprintln!("{}", GrepError::File(PathBuf::from(TERMINAL), err));
+                return ExitCode::from(TROUBLE);
+            }
+        }
+    };
+    // files that cannot be rewritten are reported, and the others are still rewritten
+    let mut has_file_errors = false;
+    let mut on_file_error = |err: GrepError| {
+        has_file_errors = true;
+        eprintln!("{}", err);
+    };
+    if let Err(err) =
+        replace::replace_in_files(&config, &mut input, &mut io::stdout(), &mut on_file_error)
+    {
+        eprintln!("{}", err);
+        return ExitCode::from(TROUBLE);
+    }
+    if has_file_errors {
+        return ExitCode::from(TROUBLE);
+    }
+    ExitCode::SUCCESS
+}
+
+fn run_index(args: &[String]) -> ExitCode {
+    let result = match (args.get(2).map(String::as_str), args.len()) {
+        (Some("build"), 4) => index::build(Path::new(&args[3]))
+            .map(|num_files| println!("indexed {} files", num_files)),
+        #[cfg(target_os = "linux")]
+        (Some("watch"), 4) => index::watch(Path::new(&args[3]), &|| false),
+        _ => {
+            print_usage();
+            return ExitCode::from(TROUBLE);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(TROUBLE)
+        }
+    }
+}
+
+fn run_serve(args: &[String]) -> ExitCode {
+    if args.len() != 4 || args[2] != "--bind" {
+        print_usage();
+        return ExitCode::from(TROUBLE);
+    }
+    let listener = match TcpListener::bind(&args[3]) {
+        Ok(x) => x,
+        Err(err) => {
+            eprintln!("Bind error: {}: {}", args[3], err);
+            return ExitCode::from(TROUBLE);
+        }
+    };
+    if let Err(err) = serve::serve(listener) {
+        eprintln!("Serve error: {}", err);
+        return ExitCode::from(TROUBLE);
+    }
+    ExitCode::SUCCESS
+}
+
+fn print_usage() {
+    eprintln!("\
+usage: minigrep [options] [query_string] filename...
+       minigrep replace [replace options] [options] -r TEMPLATE query_string filename...
+       minigrep index build DIR
+       minigrep index watch DIR
+       minigrep serve --bind ADDRESS:PORT
+  options:
+    -i, --ignore-case
+                 ignore case distinctions in both the query string and the file contents
+    -w, --word-regexp
+                 match only whole words
+    -v, --invert-match
+                 select non-matching lines
+    -n, --line-number
+                 print line numbers with output lines
+    -c, --count  print only a count of matching lines; not with -n, -o, -r, -A, -B or -C
+    -q, --quiet  print nothing and stop at the first selected line; not with -c, -n, -o,
+                 -r, -A, -B or -C
+    -s, --no-messages
+                 do not report files that cannot be searched, which are skipped
+    -e, --regexp pattern
+                 set the query string; may be repeated, and then every argument is a filename
+    -o, --only-matching
+                 print only the matched parts of matching lines, one per line; not with -v,
+                 -A, -B or -C
+    -r, --replace TEMPLATE
+                 print matches replaced by TEMPLATE; `$1` and `${{name}}` refer to
+                 capture groups (files are not modified)
+    --preserve-case
+                 with -r, match every case style of the query (foo_bar, fooBar, FooBar,
+                 FOO_BAR, foo-bar) and give each replacement the case of its match
+    -A, --after-context N
+                 print N lines of trailing context after matching lines
+    -B, --before-context N
+                 print N lines of leading context before matching lines
+    -C, --context N
+                 print N lines of context surrounding matching lines
+    -z, --search-zip
+                 search members of tar, tar.gz and zip archives
+    --archive-depth N
+                 open nested archives up to N levels deep (default 3)
+    --archive-max-ratio N
+                 reject archive members that decompress to more than N times their compressed size,
+                 and archives whose members together exceed N times the archive's size (default 100)
+    --no-config  ignore the defaults from the config file and MINIGREP_OPTS
+    --follow     keep watching the files like `tail -F` and print matching lines as they
+                 are appended; a rotated or truncated file is searched again from its start,
+                 and a missing or unreadable file is reported and tried again;
+                 not with -c, -q, --json, --csv, --tsv, --json-path, -z, -t, -T, --index,
+                 --state-file, --max-filesize, --newer-than, --older-than or --owner
+    --index      with a directory as filename, search only the files whose trigrams, as
+                 recorded by `minigrep index build`, can match the query; files changed
+                 or added since the index was updated are always searched; `minigrep
+                 index watch` keeps the index of DIR up to date as files change
+    --include GLOB
+                 search only the files matching GLOB among those found in directories
+                 and by globs; may be repeated
+    --exclude GLOB
+                 skip the files matching GLOB among those found in directories and by globs
+    --exclude-dir GLOB
+                 do not search directories matching GLOB
+    --iglob GLOB like --include, or like --exclude for `!GLOB`, but ignoring case; as in a
+                 .gitignore, the last of these rules that matches a file decides, and a
+                 GLOB without a `/` matches the file name rather than the path below the
+                 directory or the start of the glob; files named directly are not filtered
+    -t, --type TYPE
+                 search only files of TYPE among those found in directories and by globs;
+                 may be repeated
+    -T, --type-not TYPE
+                 skip files of TYPE among those found in directories and by globs
+    --type-add TYPE:GLOB
+                 add GLOB to the files of TYPE, which is created if needed
+    --type-list  print the file types with their globs; a file without an extension also
+                 has the type of the interpreter in its `#!` line, such as sh or py
+    --max-filesize SIZE
+                 skip files larger than SIZE bytes; K, M and G multiply by powers of 1024
+    --newer-than AGE|DATE
+                 search only files modified within AGE, such as 30m, 2h, 1d12h or 2w, or
+                 after DATE, given in UTC as 2024-01-31 or 2024-01-31T08:00:00
+    --older-than AGE|DATE
+                 search only files modified before AGE ago or before DATE
+    --newer FILE search only files modified after FILE was
+    --owner USER search only files owned by USER, a name or a numeric user id
+    --debug      explain on stderr why each file or directory was skipped
+    --state-file FILE
+                 search only the lines added since the last run with the same FILE, which
+                 records the offset, inode and line number reached in each file and a hash
+                 of the bytes before that offset; a replaced or truncated file is searched
+                 again from its start; not with -z, --csv, --tsv or --json-path
+    --json       print results as JSON Lines in ripgrep's message format; not with -c, -o
+                 or -q
+    --csv        parse input as CSV and print whole matching records
+    --tsv        parse input as TSV and print whole matching records
+    --field F    in CSV/TSV mode, match only column F, given by header name or 1-based index;
+                 a name makes the first row a header that is not searched
+    --header     in CSV/TSV mode, treat the first row as a header and print it before the
+                 matching records
+    --json-path SELECTOR
+                 search only the JSON values at SELECTOR (e.g. `.items[].name`) and
+                 print each match with its path and line number; .ndjson/.jsonl files
+                 are read one record per line
+    --           end the options; every later argument is the query or a filename
+  Options may also follow the query and filenames. Long options take their value as
+  `--context 3` or `--context=3`, short ones as `-C 3` or `-C3`. A filename may be a file,
+  a directory, whose files are searched recursively except for hidden ones, or a glob
+  such as `src/**/*.{{rs,toml}}` or `logs/2024-[01][0-9]/*.log`, whose files are searched
+  in sorted order; a glob that matches nothing is reported like a missing file, a file
+  that is not UTF-8 text is skipped unless named directly, and a file named more than
+  once is searched once.
+  exit status:
+    0 when a line was selected, 1 when none was and 2 on an error, including a file
+    that could not be searched; with -q, a selected line still gives 0
+  defaults:
+    options in $MINIGREP_CONFIG_PATH (or $XDG_CONFIG_HOME/minigrep/config), one or more
+    per line with `#` comments, and then in $MINIGREP_OPTS go before the command line
+    options, which win: a value such as -C 2 given again replaces the default, and
+    --no-FLAG, such as --no-ignore-case, turns off a flag; a value with spaces is quoted
+    as in the shell, such as --exclude \"My Docs/*\"
+  serve:
+    answers `POST /search` with a body such as `{{\"query\": \"fn main\", \"options\": [\"-i\"],
+    \"paths\": [\"src/*.rs\"]}}`, streaming the messages of --json as chunked NDJSON; every
+    file the user can read is searchable, so bind to a loopback address such as 127.0.0.1;
+    `options` may only hold search options such as -i, -w, -v, -C, --include or -t, and
+    others such as --state-file, --follow or --index are refused
+  replace options:
+    --dry-run    print a unified diff instead of rewriting files
+    --interactive
+                 show each match with the -A/-B/-C context and replace it only when
+                 answered y (yes), n (no), a (all remaining) or q (quit) on the terminal,
+                 or on stdin when the answers are piped in
+    --backup-suffix SUFFIX
+                 keep a copy of each rewritten file at <filename>SUFFIX
+  every match in every line of the files is replaced, so -v, -o, --json, --csv, --tsv,
+  --json-path, -z, --follow, --index and --state-file are refused");
 }
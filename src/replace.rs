@@ -0,0 +1,286 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{self, PathBuf},
+};
+
+use super::atomic_file;
+use super::diff::{self, LineEdit};
+use super::file_filter;
+use super::file_meta;
+use super::grep::{self, GrepError};
+use super::grep_config::{ConfigError, GrepConfig, GrepOption, TableFormat};
+
+pub struct ReplaceConfig {
+    pub grep: GrepConfig,
+    pub dry_run: bool,
+    // keep a copy of every rewritten file at `<path><suffix>`
+    pub backup_suffix: Option<String>,
+    // ask before applying each match
+    pub interactive: bool,
+}
+
+impl ReplaceConfig {
+    /// Parses `minigrep replace [--dry-run] [--interactive] [--backup-suffix SUFFIX] [options] -r TEMPLATE query filename`.
+    pub fn new(args: &[String]) -> Result<ReplaceConfig, ConfigError> {
+        let mut dry_run = false;
+        let mut interactive = false;
+        let mut backup_suffix = None;
+        // the program name stays in front for `GrepConfig::new`
+        let mut grep_args: Vec<String> = args.iter().take(1).cloned().collect();
+        let mut arg_index = 2;
+        while arg_index < args.len() {
+            match args[arg_index].as_str() {
+                "--" => {
+                    // the rest are queries and filenames, which `GrepConfig::new` sees after `--`
+                    grep_args.extend_from_slice(&args[arg_index..]);
+                    break;
+                }
+                "--dry-run" => dry_run = true,
+                "--interactive" => interactive = true,
+                "--backup-suffix" => {
+                    arg_index += 1;
+                    match args.get(arg_index) {
+                        Some(suffix) if !suffix.is_empty() => backup_suffix = Some(suffix.clone()),
+                        Some(suffix) => {
+                            return Err(ConfigError::IllegalOptionValue(suffix.clone()))
+                        }
+                        None => return Err(ConfigError::MissingArg("backup suffix".to_string())),
+                    }
+                }
+                _ => grep_args.push(args[arg_index].clone()),
+            }
+            arg_index += 1;
+        }
+        let grep = GrepConfig::new(&grep_args)?;
+        if grep.replacement.is_none() {
+            return Err(ConfigError::MissingArg("replacement".to_string()));
+        }
+        if let Some(option) = replace_conflict(&grep) {
+            return Err(ConfigError::Conflict(
+                "replace".to_string(),
+                option.to_string(),
+            ));
+        }
+        Ok(ReplaceConfig {
+            grep,
+            dry_run,
+            backup_suffix,
+            interactive,
+        })
+    }
+}
+
+/// The first option given that the rewrite would ignore: every match of every line
+/// of the files themselves is replaced.
+fn replace_conflict(config: &GrepConfig) -> Option<&'static str> {
+    let is_csv = matches!(config.table_format, Some(TableFormat::Csv));
+    let is_tsv = matches!(config.table_format, Some(TableFormat::Tsv));
+    let conflicts = [
+        (config.options.contains(&GrepOption::Invert), "-v"),
+        (config.options.contains(&GrepOption::OnlyMatching), "-o"),
+        (config.options.contains(&GrepOption::Json), "--json"),
+        (is_csv, "--csv"),
+        (is_tsv, "--tsv"),
+        (config.json_path.is_some(), "--json-path"),
+        (config.options.contains(&GrepOption::SearchArchives), "-z"),
+        (config.options.contains(&GrepOption::Follow), "--follow"),
+        (config.options.contains(&GrepOption::Index), "--index"),
+        (config.state_file.is_some(), "--state-file"),
+    ];
+    conflicts
+        .iter()
+        .find(|(is_given, _)| *is_given)
+        .map(|(_, option)| *option)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
+/// Rewrites every file with matches, or writes a unified diff to `out` on a dry run.
+/// In interactive mode each match is shown on `out` and a y/n/a/q answer is read from `input`.
+/// A file that cannot be read or rewritten goes to `on_error`, and the other files are still rewritten.
+pub fn replace_in_files(
+    config: &ReplaceConfig,
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+    on_error: &mut dyn FnMut(GrepError),
+) -> Result<(), GrepError> {
+    let queries = grep::compile_queries(&config.grep)?;
+    let template = config.grep.replacement.as_deref();
+    let preserve_case = config.grep.options.contains(&GrepOption::PreserveCase);
+    // the answer that applies to every remaining change, after `a` or `q`
+    let mut final_answer = None;
+    for filename in &config.grep.filenames {
+        if let Some(reason) = file_meta::skip_reason(&config.grep, filename) {
+            let is_debug = config.grep.options.contains(&GrepOption::Debug);
+            file_filter::debug_skip(is_debug, filename, &reason);
+            continue;
+        }
+        let contents = match fs::read(filename) {
+            Ok(x) => x,
+            Err(err) => {
+                on_error(GrepError::File(filename.clone(), err));
+                continue;
+            }
+        };
+        let contents = match String::from_utf8(contents) {
+            Ok(x) => x,
+            // binary files found in directories and by globs are left alone, as by the search
+            Err(_err) if config.grep.found_filenames.contains(filename) => continue,
+            Err(_err) => {
+                on_error(GrepError::NotValidUTF8(filename.clone()));
+                continue;
+            }
+        };
+        let mut edits: Vec<LineEdit> = contents
+            .split_inclusive('\n')
+            .map(|line| {
+                let has_newline = line.ends_with('\n');
+                let old = line.trim_end_matches('\n').trim_end_matches('\r');
+                let submatches = grep::find_matches(&queries, old, template, preserve_case);
+                LineEdit {
+                    old: old.to_string(),
+                    new: grep::replace_submatches(old, &submatches),
+                    has_newline,
+                }
+            })
+            .collect();
+        if config.interactive {
+            for index in 0..edits.len() {
+                if edits[index].old == edits[index].new {
+                    continue;
+                }
+                // each match is asked about on its own: a refused match keeps its text,
+                // which `replace_submatches` does for a match without a replacement
+                let old = edits[index].old.clone();
+                let mut submatches = grep::find_matches(&queries, &old, template, preserve_case);
+                let replacements: Vec<Option<String>> = submatches
+                    .iter_mut()
+                    .map(|submatch| submatch.replacement.take())
+                    .collect();
+                for (match_index, replacement) in replacements.into_iter().enumerate() {
+                    let before = grep::replace_submatches(&old, &submatches);
+                    submatches[match_index].replacement = replacement;
+                    let after = grep::replace_submatches(&old, &submatches);
+                    if before == after {
+                        continue;
+                    }
+                    let answer = match &final_answer {
+                        Some(answer) => *answer,
+                        None => {
+                            let change = (before.as_str(), after.as_str());
+                            let answer = ask(config, filename, &edits, index, change, input, out)?;
+                            if answer == Answer::All || answer == Answer::Quit {
+                                final_answer = Some(answer);
+                            }
+                            answer
+                        }
+                    };
+                    if answer == Answer::No || answer == Answer::Quit {
+                        submatches[match_index].replacement = None;
+                    }
+                }
+                edits[index].new = grep::replace_submatches(&old, &submatches);
+            }
+        }
+        if edits.iter().all(|edit| edit.old == edit.new) {
+            continue;
+        }
+        if config.dry_run {
+            let diff = diff::unified_diff(&filename.to_string_lossy(), &edits);
+            if let Err(err) = out.write_all(diff.as_bytes()) {
+                return Err(GrepError::Write(err.to_string()));
+            }
+            continue;
+        }
+        let mut rewritten = String::new();
+        for (edit, line) in edits.iter().zip(contents.split_inclusive('\n')) {
+            rewritten.push_str(&edit.new);
+            // keep the original line ending
+            rewritten.push_str(&line[edit.old.len()..]);
+        }
+        if let Err(err) = write_atomically(filename, &rewritten, config.backup_suffix.as_deref()) {
+            on_error(err);
+        }
+    }
+    Ok(())
+}
+
+/// Shows the change of one match in `edits[index]`, the line before and after it,
+/// with the line's context and reads the answer. The end of input counts as `q`.
+fn ask(
+    config: &ReplaceConfig,
+    filename: &path::Path,
+    edits: &[LineEdit],
+    index: usize,
+    (before, after): (&str, &str),
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+) -> Result<Answer, GrepError> {
+    let start = index.saturating_sub(config.grep.num_pre_lines);
+    let end = (index + config.grep.num_post_lines + 1).min(edits.len());
+    let mut hunk = format!("{}:{}\n", filename.to_string_lossy(), index + 1);
+    for (line_index, edit) in edits.iter().enumerate().take(end).skip(start) {
+        if line_index == index {
+            hunk.push_str(&format!("-{}\n", before));
+            for line in after.split('\n') {
+                hunk.push_str(&format!("+{}\n", line));
+            }
+        } else {
+            hunk.push_str(&format!(" {}\n", edit.old));
+        }
+    }
+    loop {
+        let prompt = format!("{}Replace? [y,n,a,q] ", hunk);
+        if let Err(err) = out.write_all(prompt.as_bytes()).and_then(|_| out.flush()) {
+            return Err(GrepError::Write(err.to_string()));
+        }
+        let mut answer = String::new();
+        match input.read_line(&mut answer) {
+            Ok(0) | Err(_) => return Ok(Answer::Quit),
+            Ok(_) => (),
+        }
+        match answer.trim() {
+            "y" => return Ok(Answer::Yes),
+            "n" => return Ok(Answer::No),
+            "a" => return Ok(Answer::All),
+            "q" => return Ok(Answer::Quit),
+            // only the prompt is repeated
+            _ => hunk.clear(),
+        }
+    }
+}
+
+/// Replaces `filename` in one rename, so readers never see a half-written file.
+/// The original permissions are kept, and the original is copied to the backup first.
+fn write_atomically(
+    filename: &path::Path,
+    contents: &str,
+    backup_suffix: Option<&str>,
+) -> Result<(), GrepError> {
+    let write_error =
+        |err: io::Error| GrepError::Write(format!("{}: {}", filename.to_string_lossy(), err));
+    let permissions = match fs::metadata(filename) {
+        Ok(metadata) => metadata.permissions(),
+        Err(err) => return Err(write_error(err)),
+    };
+    let mut before_rename = |tmp_path: &path::Path| {
+        fs::set_permissions(tmp_path, permissions.clone())?;
+        if let Some(suffix) = backup_suffix {
+            let mut backup = filename.as_os_str().to_os_string();
+            backup.push(suffix);
+            fs::copy(filename, PathBuf::from(backup))?;
+        }
+        Ok(())
+    };
+    match atomic_file::write_with(filename, contents.as_bytes(), &mut before_rename) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(write_error(err)),
+    }
+}
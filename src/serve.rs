@@ -0,0 +1,301 @@
+// a small HTTP server that runs searches and streams their `--json` messages
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use super::grep::{self, GrepError};
+use super::grep_config::{GrepConfig, GrepConfigBuilder};
+
+const MAX_BODY_SIZE: usize = 1 << 20;
+// connections served at once; more are answered 503 right away
+const MAX_CONNECTIONS: usize = 64;
+// how long a client may take to send its request or to take the next part of the response
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The options a request may give, by long name: those that only change what is searched
+/// and how results are reported. Options that write files, follow files forever, read
+/// other configuration or raise the archive limits are refused.
+const SEARCH_OPTIONS: &[&str] = &[
+    "ignore-case",
+    "word-regexp",
+    "invert-match",
+    "line-number",
+    "regexp",
+    "after-context",
+    "before-context",
+    "context",
+    "search-zip",
+    "json",
+    "csv",
+    "tsv",
+    "field",
+    "header",
+    "json-path",
+    "include",
+    "exclude",
+    "exclude-dir",
+    "iglob",
+    "type",
+    "type-not",
+    "max-filesize",
+    "newer-than",
+    "older-than",
+    "owner",
+];
+
+/// Answers `POST /search` requests on `listener`, each on its own thread, until it fails.
+///
+/// The body is a JSON object such as
+/// `{"query": "fn main", "options": ["-i", "-C", "2"], "paths": ["src/main.rs"]}`, where
+/// `options` takes command line options that only affect the search, and `paths` the
+/// files, globs or directories to search. The response streams the messages of
+/// `--json` as chunked NDJSON while the search runs; the search is cancelled when the
+/// client disconnects.
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    let num_connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        if num_connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            num_connections.fetch_sub(1, Ordering::SeqCst);
+            let status = "503 Service Unavailable";
+            let _ = respond_error(&mut stream, status, "too many connections");
+            continue;
+        }
+        let slot = ConnectionSlot(num_connections.clone());
+        thread::spawn(move || {
+            // a client that goes away mid-response is not the server's problem
+            let _ = handle(stream);
+            drop(slot);
+        });
+    }
+    Ok(())
+}
+
+/// Frees its place in the connection count when dropped.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle(stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut out = stream;
+    let request = match read_request(&mut reader) {
+        Ok(x) => x,
+        Err(err) => return respond_error(&mut out, "400 Bad Request", &err),
+    };
+    if request.path != "/search" {
+        return respond_error(&mut out, "404 Not Found", "no such endpoint");
+    }
+    if request.method != "POST" {
+        return respond_error(&mut out, "405 Method Not Allowed", "use POST");
+    }
+    let config = match search_config(&request.body) {
+        Ok(x) => x,
+        Err(err) => return respond_error(&mut out, "400 Bad Request", &err),
+    };
+
+    // the request has been read, so the end of the client's side or a failed read means
+    // the client is gone, even while no results are written, as in a long search of one file
+    let is_disconnected = Arc::new(AtomicBool::new(false));
+    {
+        let is_disconnected = is_disconnected.clone();
+        let mut reader = reader;
+        thread::spawn(move || {
+            let mut buffer = [0; 512];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(_) => (),
+                    Err(err)
+                        if err.kind() == io::ErrorKind::WouldBlock
+                            || err.kind() == io::ErrorKind::TimedOut => {}
+                    Err(_) => break,
+                }
+            }
+            is_disconnected.store(true, Ordering::SeqCst);
+        });
+    }
+    let should_stop = || is_disconnected.load(Ordering::SeqCst);
+
+    let result = respond(&mut out, &config, &should_stop);
+    // also ends the read above, for a client that keeps its side open
+    let _ = out.shutdown(Shutdown::Both);
+    result
+}
+
+fn respond(
+    out: &mut TcpStream,
+    config: &GrepConfig,
+    should_stop: &dyn Fn() -> bool,
+) -> io::Result<()> {
+    write!(
+        out,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: application/x-ndjson\r\n\
+         Transfer-Encoding: chunked\r\n\
+         Connection: close\r\n\r\n"
+    )?;
+    let mut chunked = ChunkedWriter { out };
+    match grep::stream_json(config, &mut chunked, should_stop) {
+        Ok(()) => (),
+        Err(GrepError::Write(_)) => return Ok(()),
+        Err(err) => {
+            let message = json!({"type": "error", "data": {"message": err.to_string()}});
+            writeln!(chunked, "{}", message)?;
+        }
+    }
+    if should_stop() {
+        return Ok(());
+    }
+    write!(out, "0\r\n\r\n")?;
+    out.flush()
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request, String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).is_err() {
+        return Err("invalid request line".to_string());
+    }
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version)) if version.starts_with("HTTP/1.") => {
+            (method.to_string(), path.to_string())
+        }
+        _ => return Err("invalid request line".to_string()),
+    };
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return Err("incomplete headers".to_string()),
+            Ok(_) => (),
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = match header.split_once(':') {
+            Some(x) => x,
+            None => return Err(format!("invalid header: {}", header)),
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = match value.trim().parse() {
+                Ok(x) if x <= MAX_BODY_SIZE => x,
+                _ => return Err("invalid content length".to_string()),
+            };
+        }
+    }
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).is_err() {
+        return Err("incomplete body".to_string());
+    }
+    Ok(Request { method, path, body })
+}
+
+/// The search configuration of a request, parsed as the command line
+/// `minigrep --json OPTIONS -e QUERY -- PATHS` would be.
+fn search_config(body: &[u8]) -> Result<GrepConfig, String> {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(x) => x,
+        Err(err) => return Err(format!("invalid JSON: {}", err)),
+    };
+    let query = match request.get("query").and_then(Value::as_str) {
+        Some(query) => query,
+        None => return Err("`query` must be a string".to_string()),
+    };
+    let strings = |name: &str| -> Result<Vec<String>, String> {
+        let values = match request.get(name) {
+            Some(Value::Array(values)) => values,
+            None => return Ok(Vec::new()),
+            Some(_) => return Err(format!("`{}` must be an array of strings", name)),
+        };
+        let mut strings = Vec::new();
+        for value in values {
+            match value.as_str() {
+                Some(s) => strings.push(s.to_string()),
+                None => return Err(format!("`{}` must be an array of strings", name)),
+            }
+        }
+        Ok(strings)
+    };
+    let options = strings("options")?;
+    let paths = strings("paths")?;
+    if paths.is_empty() {
+        return Err("`paths` must name at least one path".to_string());
+    }
+    // the response is the `--json` stream, so the options it would ignore are refused
+    let mut args = vec!["minigrep".to_string(), "--json".to_string()];
+    args.extend(options);
+    args.extend(["-e".to_string(), query.to_string(), "--".to_string()]);
+    args.extend(paths);
+    let builder = match GrepConfigBuilder::from_args(&args) {
+        Ok(x) => x,
+        Err(err) => return Err(err.to_string()),
+    };
+    for name in builder.option_names() {
+        if !SEARCH_OPTIONS.contains(&name.as_str()) {
+            return Err(format!("option not allowed: --{}", name));
+        }
+    }
+    match builder.build() {
+        Ok(config) => Ok(config),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn respond_error(out: &mut TcpStream, status: &str, message: &str) -> io::Result<()> {
+    let body = json!({ "error": message }).to_string();
+    write!(
+        out,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    out.flush()
+}
+
+/// Sends everything written to it as HTTP/1.1 chunks.
+struct ChunkedWriter<'a> {
+    out: &'a mut TcpStream,
+}
+
+impl Write for ChunkedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.out, "{:x}\r\n", buf.len())?;
+        self.out.write_all(buf)?;
+        self.out.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -0,0 +1,148 @@
+use super::grep_config::TableFormat;
+
+pub struct Record {
+    pub line_number: i32,
+    pub byte_offset: u64,
+    /// the record as it appears in the input, without the trailing line ending
+    pub text: String,
+    pub fields: Vec<String>,
+    // for each field, the span in `text` of each of its bytes, so a match in an
+    // unquoted field can be located in the record; empty when they can't be matched up
+    pub spans: Vec<Vec<(usize, usize)>>,
+}
+
+/// Parses every record of a CSV or TSV document, including the header row.
+pub fn read_records(contents: &str, format: &TableFormat) -> Result<Vec<Record>, String> {
+    let delimiter = match format {
+        TableFormat::Csv => b',',
+        TableFormat::Tsv => b'\t',
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(contents.as_bytes());
+    let mut records = Vec::new();
+    let mut record = csv::StringRecord::new();
+    loop {
+        match reader.read_record(&mut record) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) => return Err(err.to_string()),
+        }
+        let position = record.position().expect("records read from a reader have a position");
+        let start = position.byte() as usize;
+        let end = reader.position().byte() as usize;
+        let text = contents[start..end].trim_end_matches(['\r', '\n']).to_string();
+        let fields: Vec<String> = record.iter().map(|field| field.to_string()).collect();
+        let spans = field_spans(&text, delimiter, &fields);
+        records.push(Record {
+            line_number: position.line() as i32,
+            byte_offset: start as u64,
+            text,
+            fields,
+            spans,
+        });
+    }
+    Ok(records)
+}
+
+/// Finds where each byte of each field comes from in the raw record.
+/// A doubled quote inside a quoted field is one byte that spans both quotes.
+fn field_spans(text: &str, delimiter: u8, fields: &[String]) -> Vec<Vec<(usize, usize)>> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    loop {
+        let mut field = Vec::new();
+        let mut is_quoted = false;
+        if bytes.get(pos) == Some(&b'"') {
+            is_quoted = true;
+            pos += 1;
+        }
+        while pos < bytes.len() {
+            if is_quoted && bytes[pos] == b'"' {
+                if bytes.get(pos + 1) == Some(&b'"') {
+                    field.push((pos, pos + 2));
+                    pos += 2;
+                } else {
+                    is_quoted = false;
+                    pos += 1;
+                }
+            } else if !is_quoted && bytes[pos] == delimiter {
+                break;
+            } else {
+                field.push((pos, pos + 1));
+                pos += 1;
+            }
+        }
+        spans.push(field);
+        if pos >= bytes.len() {
+            break;
+        }
+        // skip the delimiter
+        pos += 1;
+    }
+    let is_consistent = spans.len() == fields.len()
+        && spans
+            .iter()
+            .zip(fields)
+            .all(|(spans, field)| spans.len() == field.len());
+    if is_consistent {
+        spans
+    } else {
+        Vec::new()
+    }
+}
+
+/// Maps the span `start..end` of field `column` to its span in the raw record.
+pub fn raw_span(
+    record: &Record,
+    column: usize,
+    start: usize,
+    end: usize,
+) -> Option<(usize, usize)> {
+    let spans = record.spans.get(column)?;
+    if start >= end || end > spans.len() {
+        return None;
+    }
+    Some((spans[start].0, spans[end - 1].1))
+}
+
+/// Tells whether the first row must be read as a header: with `--header`, or when
+/// a `--field` selector is a column name rather than a 1-based index.
+pub fn has_header(keep_header: bool, selectors: &[String]) -> bool {
+    keep_header
+        || selectors
+            .iter()
+            .any(|selector| selector.parse::<usize>().is_err())
+}
+
+/// Resolves `--field` selectors, against the header row when there is one.
+/// A selector is a column name or a 1-based column index; `None` selects every column.
+pub fn select_columns(
+    header: Option<&Record>,
+    selectors: &[String],
+) -> Result<Option<Vec<usize>>, String> {
+    if selectors.is_empty() {
+        return Ok(None);
+    }
+    let mut columns = Vec::new();
+    for selector in selectors {
+        let position =
+            header.and_then(|header| header.fields.iter().position(|name| name == selector));
+        let column = match position {
+            Some(column) => column,
+            None => match selector.parse::<usize>() {
+                Ok(index)
+                    if index >= 1 && header.is_none_or(|header| index <= header.fields.len()) =>
+                {
+                    index - 1
+                }
+                _ => return Err(format!("no such field: {}", selector)),
+            },
+        };
+        columns.push(column);
+    }
+    Ok(Some(columns))
+}
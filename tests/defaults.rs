@@ -0,0 +1,77 @@
+use std::env;
+use std::fs;
+
+use minigrep::grep_config::{self, ConfigError, GrepConfig, GrepOption};
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+// one test, since the environment is shared by every test in the process
+#[test]
+fn test_default_args() {
+    let dir = env::temp_dir().join(format!("minigrep-defaults-{}", std::process::id()));
+    fs::create_dir_all(dir.join("minigrep")).unwrap();
+    let config_path = dir.join("minigrep/config");
+    fs::write(&config_path, "# team defaults\n-i\n\n--archive-depth 5 -C 2\n").unwrap();
+    env::remove_var("MINIGREP_CONFIG_PATH");
+    env::set_var("XDG_CONFIG_HOME", &dir);
+    env::set_var("MINIGREP_OPTS", "-n");
+
+    let cli = args(&["./minigrep", "-C", "0", "line", "demo_file.txt"]);
+    let defaults = grep_config::default_args(&cli).unwrap();
+    assert_eq!(args(&["-i", "--archive-depth", "5", "-C", "2", "-n"]), defaults);
+    let mut merged = cli.clone();
+    merged.splice(1..1, defaults);
+    let config = GrepConfig::new(&merged).unwrap();
+    assert!(config.options.contains(&GrepOption::CaseInsensitive));
+    assert!(config.options.contains(&GrepOption::LineNumber));
+    assert_eq!(5, config.archive_max_depth);
+    // the command line wins
+    assert_eq!(0, config.num_pre_lines);
+    assert_eq!(0, config.num_post_lines);
+
+    // a flag from the defaults is turned off on the command line
+    let mut merged = args(&["./minigrep", "--no-ignore-case", "line", "demo_file.txt"]);
+    merged.splice(1..1, grep_config::default_args(&cli).unwrap());
+    let config = GrepConfig::new(&merged).unwrap();
+    assert!(!config.options.contains(&GrepOption::CaseInsensitive));
+    assert!(config.options.contains(&GrepOption::LineNumber));
+
+    // values are quoted as in the shell
+    fs::write(&config_path, "--exclude \"My Docs/*\" --include 'a b'\n--iglob it\\'s\n").unwrap();
+    assert_eq!(args(&["--exclude", "My Docs/*", "--include", "a b", "--iglob", "it's", "-n"]),
+        grep_config::default_args(&cli).unwrap());
+    fs::write(&config_path, "--exclude \"My Docs/*\n").unwrap();
+    assert!(matches!(grep_config::default_args(&cli), Err(ConfigError::Defaults(..))));
+
+    let no_config = args(&["./minigrep", "--no-config", "line", "demo_file.txt"]);
+    assert!(grep_config::default_args(&no_config).unwrap().is_empty());
+    assert!(GrepConfig::new(&no_config).is_ok());
+
+    // errors name the file and line
+    fs::write(&config_path, "-i\n--bogus\n").unwrap();
+    match grep_config::default_args(&cli) {
+        Err(ConfigError::Defaults(origin, err)) => {
+            assert_eq!(format!("{}:2", config_path.to_str().unwrap()), origin);
+            assert!(matches!(*err, ConfigError::UnknownOption(option) if option == "--bogus"));
+        }
+        _ => panic!("expected an error in the config file"),
+    }
+    fs::write(&config_path, "-A\n").unwrap();
+    assert!(matches!(grep_config::default_args(&cli), Err(ConfigError::Defaults(..))));
+
+    // MINIGREP_CONFIG_PATH replaces the XDG location and has to exist
+    env::set_var("MINIGREP_CONFIG_PATH", dir.join("missing"));
+    assert!(matches!(grep_config::default_args(&cli), Err(ConfigError::Defaults(..))));
+    let other = dir.join("other");
+    fs::write(&other, "-w\n").unwrap();
+    env::set_var("MINIGREP_CONFIG_PATH", &other);
+    env::set_var("MINIGREP_OPTS", "query");
+    match grep_config::default_args(&cli) {
+        Err(ConfigError::Defaults(origin, _)) => assert_eq!("MINIGREP_OPTS", origin),
+        _ => panic!("expected an error in MINIGREP_OPTS"),
+    }
+    env::remove_var("MINIGREP_OPTS");
+    assert_eq!(args(&["-w"]), grep_config::default_args(&cli).unwrap());
+}
@@ -0,0 +1,47 @@
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .arg("--no-config")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn exit_status(args: &[&str]) -> Option<i32> {
+    let output = run(args);
+    if args.contains(&"-q") {
+        assert!(output.stdout.is_empty());
+    }
+    output.status.code()
+}
+
+#[test]
+fn test_exit_status() {
+    assert_eq!(Some(0), exit_status(&["this", "demo_file.txt"]));
+    assert_eq!(Some(1), exit_status(&["no such line", "demo_file.txt"]));
+    assert_eq!(Some(0), exit_status(&["-c", "this", "demo_file.txt"]));
+    assert_eq!(Some(1), exit_status(&["-c", "no such line", "demo_file.txt"]));
+    assert_eq!(Some(0), exit_status(&["-q", "this", "demo_file.txt", "demo_file1.txt"]));
+    assert_eq!(Some(1), exit_status(&["--quiet", "no such line", "demo_file.txt"]));
+    assert_eq!(Some(2), exit_status(&["this", "no_such_file.txt"]));
+    assert_eq!(Some(2), exit_status(&["(", "demo_file.txt"]));
+    assert_eq!(Some(2), exit_status(&["--no-such-option", "this", "demo_file.txt"]));
+}
+
+#[test]
+fn test_file_errors() {
+    let output = run(&["-e", "this", "no_such_file.txt", "demo_file.txt"]);
+    assert_eq!(Some(2), output.status.code());
+    assert_eq!(3, String::from_utf8(output.stdout).unwrap().lines().count());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("no_such_file.txt: "), "{}", stderr);
+
+    let output = run(&["-s", "-e", "this", "no_such_file.txt", "demo_file.txt"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(output.stderr.is_empty());
+    // with -q, a match settles it
+    assert_eq!(Some(0), exit_status(&["-q", "-e", "this", "no_such_file.txt", "demo_file.txt"]));
+    let args = ["-q", "-e", "no such line", "no_such_file.txt", "demo_file.txt"];
+    assert_eq!(Some(2), exit_status(&args));
+}
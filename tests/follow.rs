@@ -0,0 +1,187 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use minigrep::grep::{self, GrepError};
+use minigrep::grep_config::{ConfigError, GrepConfig, GrepOption};
+
+mod common;
+use common::ScratchDir;
+
+#[derive(Clone, Default)]
+struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedOutput {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+fn append(path: &PathBuf, text: &str) {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    f.write_all(text.as_bytes()).unwrap();
+}
+
+/// Follows `config` until the output has `lines` lines or a timeout passes.
+/// Returns the output and the files that could not be read.
+fn follow_until(
+    config: GrepConfig,
+    lines: usize,
+    steps: impl FnOnce() + Send + 'static,
+) -> (String, Vec<GrepError>) {
+    let out = SharedOutput::default();
+    let writer = thread::spawn(steps);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let observed = out.clone();
+    let should_stop = || observed.text().lines().count() >= lines || Instant::now() > deadline;
+    let mut errors = Vec::new();
+    grep::follow(&config, &mut out.clone(), &should_stop, &mut |err| errors.push(err)).unwrap();
+    writer.join().unwrap();
+    (out.text(), errors)
+}
+
+#[test]
+fn test_follow_appended_lines() {
+    let dir = ScratchDir::new("follow-append");
+    let path = dir.join("app.log");
+    fs::write(&path, "error: first\ninfo: skipped\n").unwrap();
+    let options = vec![GrepOption::LineNumber].into_iter().collect();
+    let config = GrepConfig {
+        queries: vec!["error".to_string()],
+        filenames: vec![path.clone()],
+        options,
+        ..Default::default()
+    };
+    let log = path.clone();
+    let (out, errors) = follow_until(config, 3, move || {
+        thread::sleep(Duration::from_millis(300));
+        append(&log, "info: ok\nerror: sec");
+        thread::sleep(Duration::from_millis(300));
+        // the line is only searched once it is complete
+        append(&log, "ond\n");
+        // truncation starts over at line 1
+        thread::sleep(Duration::from_millis(600));
+        fs::write(&log, "error: after truncate\n").unwrap();
+    });
+    assert_eq!(
+        "1:error: first\n4:error: second\n1:error: after truncate\n",
+        out
+    );
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn test_follow_rotation_and_several_files() {
+    let dir = ScratchDir::new("follow-rotate");
+    let first = dir.join("a.log");
+    let second = dir.join("b.log");
+    fs::write(&first, "error in a\n").unwrap();
+    fs::write(&second, "").unwrap();
+    let config = GrepConfig {
+        queries: vec!["error".to_string()],
+        filenames: vec![first.clone(), second.clone()],
+        ..Default::default()
+    };
+    let (out, errors) = follow_until(config, 4, move || {
+        thread::sleep(Duration::from_millis(300));
+        append(&second, "error in b\n");
+        thread::sleep(Duration::from_millis(300));
+        // rotate: lines written to the old file before the rename are still reported
+        append(&first, "error before rotation\n");
+        fs::rename(&first, first.with_extension("log.1")).unwrap();
+        fs::write(&first, "error after rotation\n").unwrap();
+    });
+    let (a, b) = (dir.join("a.log"), dir.join("b.log"));
+    let (a, b) = (a.display(), b.display());
+    assert_eq!(
+        format!(
+            "{a}:error in a
+{b}:error in b
+{a}:error before rotation
+{a}:error after rotation
+"
+        ),
+        out
+    );
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn test_follow_missing_file() {
+    let dir = ScratchDir::new("follow-missing");
+    let first = dir.join("a.log");
+    let missing = dir.join("missing.log");
+    fs::write(&first, "error in a\n").unwrap();
+    let config = GrepConfig {
+        queries: vec!["error".to_string()],
+        filenames: vec![first.clone(), missing.clone()],
+        ..Default::default()
+    };
+    let created = missing.clone();
+    let (out, errors) = follow_until(config, 3, move || {
+        thread::sleep(Duration::from_millis(600));
+        append(&first, "error again in a\n");
+        // like `tail -F`, a missing file is followed once it appears
+        thread::sleep(Duration::from_millis(600));
+        append(&created, "error in missing\n");
+    });
+    let (a, created) = (dir.join("a.log"), dir.join("missing.log"));
+    let (a, created) = (a.display(), created.display());
+    assert_eq!(
+        format!(
+            "{a}:error in a
+{a}:error again in a
+{created}:error in missing
+"
+        ),
+        out
+    );
+    // reported once, not at every poll
+    assert!(matches!(&errors[..], [GrepError::File(path, _)] if *path == missing));
+}
+
+#[test]
+fn test_follow_rejects_ignored_options() {
+    let rejected = [
+        vec!["--json"],
+        vec!["--csv"],
+        vec!["--tsv"],
+        vec!["--json-path", ".msg"],
+        vec!["-z"],
+        vec!["-c"],
+        vec!["-q"],
+        vec!["--index"],
+        vec!["--state-file", "state"],
+        vec!["-t", "rust"],
+        vec!["-T", "rust"],
+        vec!["--max-filesize", "1K"],
+        vec!["--newer-than", "1d"],
+        vec!["--older-than", "1d"],
+        vec!["--owner", "0"],
+    ];
+    for options in rejected {
+        let mut args = vec!["./minigrep", "--follow"];
+        args.extend(&options);
+        args.extend(["error", "demo_file.txt"]);
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert_eq!(
+            Some(ConfigError::Conflict("--follow".to_string(), options[0].to_string())),
+            GrepConfig::new(&args).err());
+    }
+}
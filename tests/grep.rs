@@ -3,8 +3,11 @@
 use std::collections::HashSet;
 use std::path::PathBuf;
 
-use minigrep::grep;
-use minigrep::grep_config::{GrepConfig, GrepOption};
+use minigrep::grep::{self, GrepError, LineKind};
+use minigrep::grep_config::{ConfigError, GrepConfig, GrepOption, TableFormat};
+
+mod common;
+use common::ScratchDir;
 
 #[test]
 fn test_grep_literal_string_single_file1() {
@@ -16,6 +19,7 @@ fn test_grep_literal_string_single_file1() {
         options: HashSet::new(),
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
     assert_eq!("this line is the 1st lower case line in this file.".to_string(), matches[0]);
@@ -62,6 +66,7 @@ fn test_grep_literal_string_multiple_files1() {
         options: HashSet::new(),
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
     assert_eq!("demo_file.txt:this line is the 1st lower case line in this file.".to_string(), matches[0]);
@@ -88,6 +93,36 @@ fn test_grep_literal_string_multiple_files2() {
     assert_eq!("demo_file1.txt:And this is the last line.".to_string(), matches[5]);
 }
 
+#[test]
+fn test_grep_line_paths() {
+    let config = GrepConfig::builder()
+        .query("this")
+        .path("demo_file.txt")
+        .path("demo_file1.txt")
+        .build()
+        .unwrap();
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    let lines: Vec<grep::LineResult> = grep_result.lines().map(|(_, line)| line.clone()).collect();
+    let paths: Vec<String> = lines.iter().map(|line| line.path().display().to_string()).collect();
+    assert_eq!(vec!["demo_file.txt", "demo_file.txt", "demo_file.txt",
+        "demo_file1.txt", "demo_file1.txt", "demo_file1.txt"], paths);
+    // the same line found in two files is told apart by its path
+    assert_eq!(lines[0].text(), lines[3].text());
+    assert_ne!(lines[0], lines[3]);
+    assert_eq!(lines[0], lines[0].clone());
+    assert!(format!("{:?}", lines[0]).contains("demo_file.txt"));
+    assert!(format!("{:?}", grep_result).contains("demo_file1.txt"));
+    let config = GrepConfig::builder()
+        .query("needle")
+        .path("demo_archive.zip")
+        .option(GrepOption::SearchArchives)
+        .build()
+        .unwrap();
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    let paths: Vec<String> = grep_result.lines().map(|(_, line)| line.path().display().to_string()).collect();
+    assert_eq!(vec!["demo_archive.zip!inner/readme.txt", "demo_archive.zip!nested.tar.gz!deep/notes.txt"], paths);
+}
+
 #[test]
 fn test_grep_case_insensitive() {
     let queries = vec!["the".to_string()];
@@ -101,6 +136,7 @@ fn test_grep_case_insensitive() {
         options,
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
     assert_eq!("THIS LINE IS THE 1ST UPPER CASE LINE IN THIS FILE.".to_string(), matches[0]);
@@ -121,6 +157,7 @@ fn test_grep_regex() {
         options,
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
     assert_eq!("Two lines above this line is empty.".to_string(), matches[0]);
@@ -140,6 +177,7 @@ fn test_grep_word() {
         options,
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
     assert_eq!("THIS LINE IS THE 1ST UPPER CASE LINE IN THIS FILE.".to_string(), matches[0]);
@@ -148,6 +186,55 @@ fn test_grep_word() {
     assert_eq!("And this is the last line.".to_string(), matches[3]);
 }
 
+#[test]
+fn test_grep_word_and_case_spans() {
+    let config = GrepConfig::builder()
+        .query("line")
+        .path("demo_file.txt")
+        .option(GrepOption::CaseInsensitive)
+        .option(GrepOption::Word)
+        .build()
+        .unwrap();
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    let spans: Vec<(i32, std::ops::Range<usize>)> = grep_result
+        .lines()
+        .flat_map(|(_, line)| line.submatches().iter().map(|m| (line.line_number(), m.span())))
+        .collect();
+    // whole words only, including one at the end of a line, without the characters around them
+    assert_eq!(
+        vec![(1, 5..9), (1, 32..36), (2, 5..9), (2, 32..36), (3, 5..9), (5, 21..25), (6, 21..25)],
+        spans);
+    for (_, line) in grep_result.lines() {
+        for submatch in line.submatches() {
+            assert!(line.text()[submatch.span()].eq_ignore_ascii_case("line"));
+        }
+    }
+    let config = GrepConfig::builder()
+        .query("this")
+        .path("demo_file.txt")
+        .option(GrepOption::Word)
+        .build()
+        .unwrap();
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    let (_, line) = grep_result.lines().next().unwrap();
+    assert_eq!((2, 0..4), (line.line_number(), line.submatches()[0].span()));
+    // the query keeps its case, so its escapes and group names mean what they say
+    let config = GrepConfig::builder()
+        .query(r"(?P<Word>\bTHE\b)\S")
+        .path("demo_file.txt")
+        .option(GrepOption::CaseInsensitive)
+        .build()
+        .unwrap();
+    assert_eq!(0, grep::GrepResult::new(&config).unwrap().lines().count());
+    let config = GrepConfig::builder()
+        .query(r"(?P<Word>\bTHE\b)\s")
+        .path("demo_file.txt")
+        .option(GrepOption::CaseInsensitive)
+        .build()
+        .unwrap();
+    assert_eq!(4, grep::GrepResult::new(&config).unwrap().lines().count());
+}
+
 #[test]
 fn test_grep_invert() {
     let queries = vec![
@@ -164,6 +251,7 @@ fn test_grep_invert() {
         options,
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
     assert_eq!("d".to_string(), matches[0]);
@@ -183,6 +271,7 @@ fn test_grep_line_number() {
         options,
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
     assert_eq!("5: * e - go to the end of the current word.
@@ -207,6 +296,7 @@ fn test_grep_count1() {
         options,
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
     assert_eq!("3".to_string(), s);
@@ -227,6 +317,7 @@ fn test_grep_count2() {
         options,
         num_pre_lines: 0,
         num_post_lines: 0,
+        ..Default::default()
     };
     let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
     assert_eq!("2".to_string(), s);
@@ -246,6 +337,7 @@ fn test_grep_after() {
         options,
         num_pre_lines: 0,
         num_post_lines: 3,
+        ..Default::default()
     };
     let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
     assert_eq!("Example to show the difference between WORD and word
@@ -267,6 +359,7 @@ fn test_grep_before() {
         options,
         num_pre_lines: 2,
         num_post_lines: 0,
+        ..Default::default()
     };
     let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
     assert_eq!("Example to show the difference between WORD and word
@@ -287,6 +380,7 @@ fn test_grep_around() {
         options,
         num_pre_lines: 2,
         num_post_lines: 2,
+        ..Default::default()
     };
     let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
     assert_eq!("word - word consists of a sequence of letters, digits and underscores.
@@ -295,3 +389,923 @@ Example to show the difference between WORD and word
 
  * 192.168.1.1 - single WORD".to_string(), s);
 }
+
+#[test]
+fn test_grep_zip_archive() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "-z".to_string(),
+        "needle".to_string(),
+        "demo_archive.zip".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(true);
+    assert_eq!(vec![
+        "demo_archive.zip!inner/readme.txt:2:find the needle here".to_string(),
+        "demo_archive.zip!nested.tar.gz!deep/notes.txt:2:the needle is here too".to_string()], matches);
+}
+
+#[test]
+fn test_grep_tar_gz_archive() {
+    let queries = vec!["needle".to_string()];
+    let filenames = vec![PathBuf::from("demo_archive.tar.gz".to_string())];
+    let options = vec![
+        GrepOption::SearchArchives].into_iter().collect();
+    let config = GrepConfig {
+        queries,
+        filenames,
+        options,
+        ..Default::default()
+    };
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
+    assert_eq!(vec!["demo_archive.tar.gz!docs/a.txt:needle in a".to_string()], matches);
+}
+
+#[test]
+fn test_grep_archive_depth() {
+    let queries = vec!["needle".to_string()];
+    let filenames = vec![PathBuf::from("demo_archive.zip".to_string())];
+    let options = vec![
+        GrepOption::SearchArchives].into_iter().collect();
+    let config = GrepConfig {
+        queries,
+        filenames,
+        options,
+        archive_max_depth: 1,
+        ..Default::default()
+    };
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
+    assert_eq!(vec!["demo_archive.zip!inner/readme.txt:find the needle here".to_string()], matches);
+}
+
+#[test]
+fn test_grep_archive_ratio() {
+    let queries = vec!["0".to_string()];
+    let filenames = vec![PathBuf::from("demo_bomb.zip".to_string())];
+    let options = vec![
+        GrepOption::SearchArchives].into_iter().collect();
+    let config = GrepConfig {
+        queries,
+        filenames,
+        options,
+        ..Default::default()
+    };
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    assert!(matches!(grep_result.errors(), [grep::GrepError::Archive(_)]));
+}
+
+#[test]
+fn test_grep_archive_total_size() {
+    use std::io::Write;
+    // a tar.gz of zeros, within the ratio on its own
+    let mut zeros = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best()));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(100_000);
+    header.set_mode(0o644);
+    header.set_cksum();
+    zeros.append_data(&mut header, "zeros.txt", &vec![b'0'; 100_000][..]).unwrap();
+    let zeros = zeros.into_inner().unwrap().finish().unwrap();
+    // copies of it in a tar, which deflates well because they repeat
+    let mut copies = tar::Builder::new(Vec::new());
+    for index in 0..16 {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(zeros.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        copies.append_data(&mut header, format!("{}.tar.gz", index), &zeros[..]).unwrap();
+    }
+    let copies = copies.into_inner().unwrap();
+    let dir = std::env::temp_dir().join(format!("minigrep-archive-total-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("nested.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+    zip.start_file("copies.tar", zip::write::SimpleFileOptions::default()).unwrap();
+    zip.write_all(&copies).unwrap();
+    zip.finish().unwrap();
+
+    let mut config = GrepConfig {
+        queries: vec!["0".to_string()],
+        filenames: vec![path],
+        options: vec![GrepOption::SearchArchives].into_iter().collect(),
+        archive_max_ratio: 1_000_000,
+        ..Default::default()
+    };
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    assert!(grep_result.errors().is_empty());
+    assert_eq!(16, grep_result.files().count());
+    // every member is within this ratio, but all of them together are not
+    config.archive_max_ratio = 2000;
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(grep_result.errors(), [grep::GrepError::Archive(_)]));
+}
+
+#[test]
+fn test_grep_json() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "--json".to_string(),
+        "-A".to_string(),
+        "1".to_string(),
+        "lines".to_string(),
+        "demo_file.txt".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let messages: Vec<serde_json::Value> = grep::GrepResult::new(&config).unwrap()
+        .to_json_lines()
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let types: Vec<&str> = messages.iter().map(|m| m["type"].as_str().unwrap()).collect();
+    assert_eq!(vec!["begin", "match", "context", "end", "summary"], types);
+    assert_eq!("demo_file.txt", messages[0]["data"]["path"]["text"]);
+    let data = &messages[1]["data"];
+    assert_eq!("Two lines above this line is empty.\n", data["lines"]["text"]);
+    assert_eq!(5, data["line_number"]);
+    assert_eq!(170, data["absolute_offset"]);
+    assert_eq!("lines", data["submatches"][0]["match"]["text"]);
+    assert_eq!(4, data["submatches"][0]["start"]);
+    assert_eq!(9, data["submatches"][0]["end"]);
+    assert_eq!(6, messages[2]["data"]["line_number"]);
+    assert_eq!(1, messages[3]["data"]["stats"]["matched_lines"]);
+    assert_eq!(232, messages[4]["data"]["stats"]["bytes_searched"]);
+    // files without a match have no begin and end, but are counted in the summary
+    let args = vec![
+        "./minigrep".to_string(),
+        "--json".to_string(),
+        "needle".to_string(),
+        "demo_file.txt".to_string(),
+        "demo_file1.txt".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let messages: Vec<serde_json::Value> = grep::GrepResult::new(&config).unwrap()
+        .to_json_lines()
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(1, messages.len());
+    assert_eq!("summary", messages[0]["type"]);
+    assert_eq!(2, messages[0]["data"]["stats"]["searches"]);
+    assert_eq!(0, messages[0]["data"]["stats"]["searches_with_match"]);
+}
+
+#[test]
+fn test_grep_csv_field() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "--csv".to_string(),
+        "--field".to_string(),
+        "status".to_string(),
+        "--header".to_string(),
+        "open".to_string(),
+        "demo_table.csv".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
+    assert_eq!("id,name,status,note
+1,alpha,open,\"contains, a comma\"
+3,gamma,open,plain".to_string(), s);
+}
+
+#[test]
+fn test_grep_csv_quoting() {
+    let queries = vec!["closed,open".to_string()];
+    let filenames = vec![PathBuf::from("demo_table.csv".to_string())];
+    let config = GrepConfig {
+        queries,
+        filenames,
+        table_format: Some(TableFormat::Csv),
+        ..Default::default()
+    };
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
+    assert!(matches.is_empty());
+
+    let queries = vec!["later".to_string()];
+    let filenames = vec![PathBuf::from("demo_table.csv".to_string())];
+    let config = GrepConfig {
+        queries,
+        filenames,
+        table_format: Some(TableFormat::Csv),
+        fields: vec!["4".to_string()],
+        ..Default::default()
+    };
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(true);
+    assert_eq!(vec!["3:2,beta,closed,\"spans\ntwo lines, open later\"".to_string()], matches);
+}
+
+#[test]
+fn test_grep_tsv_count() {
+    let queries = vec!["open".to_string()];
+    let filenames = vec![PathBuf::from("demo_table.tsv".to_string())];
+    let options = vec![
+        GrepOption::Count,
+        GrepOption::KeepHeader].into_iter().collect();
+    let config = GrepConfig {
+        queries,
+        filenames,
+        options,
+        table_format: Some(TableFormat::Tsv),
+        fields: vec!["status".to_string()],
+        ..Default::default()
+    };
+    let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
+    assert_eq!("1".to_string(), s);
+}
+
+#[test]
+fn test_grep_csv_without_header() {
+    // without --header or a field name, the first row is a record like any other
+    let config = GrepConfig::builder()
+        .query("^id$")
+        .path("demo_table.csv")
+        .table_format(TableFormat::Csv)
+        .field("1")
+        .build()
+        .unwrap();
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(true);
+    assert_eq!(vec!["1:id,name,status,note".to_string()], matches);
+}
+
+#[test]
+fn test_grep_csv_submatches() {
+    let config = GrepConfig::builder()
+        .query("open|comma")
+        .path("demo_table.csv")
+        .table_format(TableFormat::Csv)
+        .build()
+        .unwrap();
+    let result = grep::GrepResult::new(&config).unwrap();
+    let spans: Vec<(i32, Vec<&str>)> = result
+        .lines()
+        .map(|(_, line)| (
+            line.line_number(),
+            line.submatches().iter().map(|submatch| &line.text()[submatch.span()]).collect()))
+        .collect();
+    assert_eq!(vec![
+        (2, vec!["open", "comma"]),
+        (3, vec!["open"]),
+        (5, vec!["open"]),
+        (6, vec!["open"])], spans);
+    let first = result.lines().next().unwrap().1;
+    assert_eq!(vec![8..12, 26..31], first.submatches().iter().map(|submatch| submatch.span()).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_grep_json_path() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "--json-path".to_string(),
+        ".items[].name".to_string(),
+        "-i".to_string(),
+        "widget".to_string(),
+        "demo_data.json".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    assert_eq!("4:.items[0].name: Widget
+6:.items[2].name: widget stand".to_string(), grep_result.to_string(&config));
+    // each value has the offset of the line it starts on
+    let offsets: Vec<u64> = grep_result.lines().map(|(_, line)| line.byte_offset()).collect();
+    assert_eq!(vec![38, 129], offsets);
+}
+
+#[test]
+fn test_grep_json_path_invert_word() {
+    let queries = vec!["widget".to_string()];
+    let filenames = vec![PathBuf::from("demo_data.json".to_string())];
+    let options = vec![
+        GrepOption::Invert,
+        GrepOption::Word].into_iter().collect();
+    let config = GrepConfig {
+        queries,
+        filenames,
+        options,
+        json_path: Some(".items[].name".to_string()),
+        ..Default::default()
+    };
+    let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
+    assert_eq!("4:.items[0].name: Widget
+5:.items[1].name: gadget".to_string(), s);
+}
+
+#[test]
+fn test_grep_json_path_ndjson() {
+    let queries = vec!["disk".to_string()];
+    let filenames = vec![PathBuf::from("demo_data.ndjson".to_string())];
+    let config = GrepConfig {
+        queries,
+        filenames,
+        json_path: Some(".msg".to_string()),
+        ..Default::default()
+    };
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    assert_eq!("2:.msg: disk full
+4:.msg: disk almost full".to_string(), grep_result.to_string(&config));
+    let offsets: Vec<u64> = grep_result.lines().map(|(_, line)| line.byte_offset()).collect();
+    assert_eq!(vec![44, 84], offsets);
+}
+
+#[test]
+fn test_grep_json_path_deep_nesting() {
+    let dir = std::env::temp_dir().join(format!("minigrep-json-depth-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("deep.json");
+    let depth = 100_000;
+    std::fs::write(&path, format!("{}{}", "[".repeat(depth), "]".repeat(depth))).unwrap();
+    let config = GrepConfig {
+        queries: vec!["x".to_string()],
+        filenames: vec![path],
+        json_path: Some(".".to_string()),
+        ..Default::default()
+    };
+    let result = grep::GrepResult::new(&config).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    match result.errors() {
+        [GrepError::JsonPath(_, message)] => assert!(message.contains("nested too deeply")),
+        _ => panic!("expected a JSON path error"),
+    }
+}
+
+#[test]
+fn test_grep_bad_json_and_table_files() {
+    let dir = std::env::temp_dir().join(format!("minigrep-bad-files-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let bad_json = dir.join("bad.json");
+    std::fs::write(&bad_json, "{\"items\": [\n").unwrap();
+    let no_status = dir.join("no_status.csv");
+    std::fs::write(&no_status, "id,name\n1,open\n").unwrap();
+
+    // each bad file is reported with its path, and the good ones after it are still searched
+    let config = GrepConfig::builder()
+        .query("widget")
+        .path(&bad_json)
+        .path("demo_data.json")
+        .json_path(".items[].name")
+        .build()
+        .unwrap();
+    let result = grep::GrepResult::new(&config).unwrap();
+    assert!(matches!(result.errors(), [GrepError::JsonPath(path, _)] if *path == bad_json));
+    assert_eq!(vec!["6:.items[2].name: widget stand".to_string()], result.to_lines(true));
+
+    let config = GrepConfig::builder()
+        .query("open")
+        .path(&no_status)
+        .path("demo_table.csv")
+        .table_format(TableFormat::Csv)
+        .field("status")
+        .build()
+        .unwrap();
+    let result = grep::GrepResult::new(&config).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(result.errors(), [GrepError::Table(path, _)] if *path == no_status));
+    assert_eq!(2, result.lines().count());
+}
+
+#[test]
+fn test_grep_replace() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "-n".to_string(),
+        "-r".to_string(),
+        "[$1]".to_string(),
+        "go to the (\\w+)".to_string(),
+        "demo_text.txt".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(true);
+    assert_eq!("5: * e - [end] of the current word.".to_string(), matches[0]);
+    assert_eq!("9: * w - [next] word.".to_string(), matches[4]);
+}
+
+#[test]
+fn test_grep_replace_only_matching() {
+    let queries = vec![
+        "(?P<first>this) (?P<second>line)".to_string(),
+        "last".to_string()];
+    let filenames = vec![PathBuf::from("demo_file.txt".to_string())];
+    let options = vec![
+        GrepOption::CaseInsensitive,
+        GrepOption::OnlyMatching].into_iter().collect();
+    let config = GrepConfig {
+        queries,
+        filenames,
+        options,
+        replacement: Some("${second}-${first}".to_string()),
+        ..Default::default()
+    };
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
+    assert_eq!(vec![
+        "LINE-THIS".to_string(),
+        "line-this".to_string(),
+        "Line-This".to_string(),
+        "line-this".to_string(),
+        "-".to_string()], matches);
+}
+
+#[test]
+fn test_grep_only_matching() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "-o".to_string(),
+        "-e".to_string(),
+        "lines?".to_string(),
+        "-e".to_string(),
+        "empty".to_string(),
+        "demo_file.txt".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
+    assert_eq!(vec!["line", "line", "lines", "line", "empty", "line"], matches);
+}
+
+#[test]
+fn test_grep_long_options() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "--ignore-case".to_string(),
+        "--after-context=1".to_string(),
+        "--line-number".to_string(),
+        "--regexp".to_string(),
+        "EMPTY".to_string(),
+        "demo_file.txt".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let short_args = vec![
+        "./minigrep".to_string(),
+        "-inA1".to_string(),
+        "-eEMPTY".to_string(),
+        "demo_file.txt".to_string()];
+    let short_config = GrepConfig::new(&short_args).unwrap();
+    let s = grep::GrepResult::new(&config).unwrap().to_string(&config);
+    assert_eq!(s, grep::GrepResult::new(&short_config).unwrap().to_string(&short_config));
+    assert_eq!(1, config.num_post_lines);
+    assert!(config.options.contains(&GrepOption::CaseInsensitive));
+    assert!(config.options.contains(&GrepOption::LineNumber));
+}
+
+#[test]
+fn test_grep_options_after_operands() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "this".to_string(),
+        "demo_file.txt".to_string(),
+        "-n".to_string(),
+        "--context".to_string(),
+        "2".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    assert_eq!(vec!["this".to_string()], config.queries);
+    assert!(config.options.contains(&GrepOption::LineNumber));
+    assert_eq!((2, 2), (config.num_pre_lines, config.num_post_lines));
+    // after `--`, an argument that looks like an option is the query
+    let args = vec![
+        "./minigrep".to_string(),
+        "-n".to_string(),
+        "--".to_string(),
+        "-c".to_string(),
+        "demo_file.txt".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    assert_eq!(vec!["-c".to_string()], config.queries);
+    assert!(!config.options.contains(&GrepOption::Count));
+}
+
+#[test]
+fn test_grep_malformed_args() {
+    let cases: Vec<(Vec<&str>, ConfigError)> = vec![
+        (vec![], ConfigError::MissingArg("query".to_string())),
+        (vec!["this"], ConfigError::MissingArg("filename".to_string())),
+        (vec!["this", "demo_file.txt", "-e"], ConfigError::MissingArg("-e".to_string())),
+        (vec!["this", "demo_file.txt", "--after-context"], ConfigError::MissingArg("--after-context".to_string())),
+        (vec!["-A", "x", "this", "demo_file.txt"], ConfigError::IllegalOptionValue("x".to_string())),
+        (vec!["--count=3", "this", "demo_file.txt"], ConfigError::IllegalOptionValue("--count=3".to_string())),
+        (vec!["--colour", "this", "demo_file.txt"], ConfigError::UnknownOption("--colour".to_string())),
+        (vec!["-ié", "this", "demo_file.txt"], ConfigError::UnknownOption("é".to_string())),
+        (vec!["this", ""], ConfigError::Filename("".to_string())),
+    ];
+    for (arguments, expected) in cases {
+        let mut args = vec!["./minigrep".to_string()];
+        args.extend(arguments.iter().map(|arg| arg.to_string()));
+        match GrepConfig::new(&args) {
+            Err(err) => assert_eq!(expected, err, "{:?}", arguments),
+            Ok(_) => panic!("{:?} should not parse", arguments),
+        }
+    }
+}
+
+#[test]
+fn test_grep_multiple_operands() {
+    let args = vec![
+        "./minigrep".to_string(),
+        "this".to_string(),
+        "demo_file1.txt".to_string(),
+        "demo_file.txt".to_string(),
+        "./demo_file1.txt".to_string(),
+        "demo_*.txt".to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    // the order given is kept, and the glob adds only the file not named before
+    let expected: Vec<PathBuf> = ["demo_file1.txt", "demo_file.txt", "demo_text.txt"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(expected, config.filenames);
+}
+
+#[test]
+fn test_grep_directory_operand() {
+    let dir = std::env::temp_dir().join(format!("minigrep-dir-operand-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::create_dir_all(dir.join(".hidden")).unwrap();
+    std::fs::write(dir.join("b.txt"), "needle b\n").unwrap();
+    std::fs::write(dir.join("sub/a.txt"), "needle a\n").unwrap();
+    std::fs::write(dir.join(".hidden/c.txt"), "needle c\n").unwrap();
+    let args = vec![
+        "./minigrep".to_string(),
+        "needle".to_string(),
+        dir.to_str().unwrap().to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    assert_eq!(vec![dir.join("b.txt"), dir.join("sub/a.txt")], config.filenames);
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
+    let expected = vec![
+        format!("{}:needle b", dir.join("b.txt").display()),
+        format!("{}:needle a", dir.join("sub/a.txt").display())];
+    assert_eq!(expected, matches);
+}
+
+#[test]
+fn test_grep_binary_files_found() {
+    let dir = ScratchDir::new("binary-found");
+    dir.file("a.txt", "needle a\n");
+    let binary = dir.file("b.bin", b"needle \xff\n");
+    // binary files in a directory or matching a glob are skipped without an error
+    for operand in [dir.to_str().unwrap().to_string(), format!("{}/*", dir.to_str().unwrap())] {
+        let args = vec!["./minigrep".to_string(), "needle".to_string(), operand];
+        let config = GrepConfig::new(&args).unwrap();
+        let grep_result = grep::GrepResult::new(&config).unwrap();
+        assert!(grep_result.errors().is_empty());
+        assert_eq!(1, grep_result.lines().count());
+    }
+    // a binary file named directly is still reported
+    let args = vec!["./minigrep".to_string(), "needle".to_string(), binary.to_str().unwrap().to_string()];
+    let config = GrepConfig::new(&args).unwrap();
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    assert!(matches!(grep_result.errors(), [GrepError::NotValidUTF8(path)] if *path == binary));
+}
+
+#[test]
+fn test_grep_glob_operands() {
+    let dir = std::env::temp_dir().join(format!("minigrep-glob-{}", std::process::id()));
+    for file in ["src/main.rs", "src/grep/follow.rs", "src/.hidden.rs", "logs/2024-01/app.log",
+                 "logs/2024-02/app.log", "logs/2023-12/app.log", "a.txt", "b.txt", "c.txt"] {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "needle\n").unwrap();
+    }
+    let dir = dir.to_str().unwrap();
+    let expand = |pattern: &str| {
+        let args = vec![
+            "./minigrep".to_string(),
+            "needle".to_string(),
+            format!("{}/{}", dir, pattern)];
+        let config = GrepConfig::new(&args).unwrap();
+        let prefix = format!("{}/", dir);
+        config.filenames.iter()
+            .map(|path| path.to_str().unwrap().strip_prefix(&prefix).unwrap().to_string())
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(vec!["src/grep/follow.rs", "src/main.rs"], expand("src/**/*.rs"));
+    assert_eq!(vec!["logs/2024-01/app.log", "logs/2024-02/app.log"], expand("logs/2024-*/app.log"));
+    assert_eq!(vec!["a.txt", "c.txt"], expand("{c,a}.txt"));
+    assert_eq!(vec!["b.txt", "c.txt"], expand("[b-z].txt"));
+    assert_eq!(vec!["src/.hidden.rs"], expand("src/.*.rs"));
+    // a glob that matches nothing is reported when searching, and the other files are searched
+    let args = vec![
+        "./minigrep".to_string(),
+        "needle".to_string(),
+        format!("{}/*.md", dir),
+        format!("{}/a.txt", dir)];
+    let config = GrepConfig::new(&args).unwrap();
+    let result = grep::GrepResult::new(&config).unwrap();
+    assert!(matches!(result.errors(),
+        [GrepError::File(path, err)] if path.to_str() == Some(&format!("{}/*.md", dir)) && err.kind() == std::io::ErrorKind::NotFound));
+    assert_eq!(1, result.files().count());
+}
+
+#[test]
+fn test_grep_file_rules() {
+    let dir = std::env::temp_dir().join(format!("minigrep-file-rules-{}", std::process::id()));
+    for file in ["src/a.rs", "src/b.min.js", "target/x.rs", "web/A.JS", "web/c.js"] {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "needle\n").unwrap();
+    }
+    let dir = dir.to_str().unwrap().to_string();
+    let select = |options: &[&str], operand: &str| {
+        let mut args = vec!["./minigrep".to_string(), "needle".to_string()];
+        args.extend(options.iter().map(|option| option.to_string()));
+        args.push(format!("{}{}", dir, operand));
+        let config = GrepConfig::new(&args).unwrap();
+        let prefix = format!("{}/", dir);
+        config.filenames.iter()
+            .map(|path| path.to_str().unwrap().strip_prefix(&prefix).unwrap().to_string())
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(vec!["src/a.rs", "target/x.rs"], select(&["--include", "*.rs"], ""));
+    assert_eq!(vec!["src/a.rs"], select(&["--include=*.rs", "--exclude-dir=target"], ""));
+    assert_eq!(vec!["web/A.JS", "web/c.js"], select(&["--iglob", "*.js", "--exclude", "*.min.js"], ""));
+    // the last rule that matches decides
+    assert_eq!(vec!["src/b.min.js"], select(&["--exclude", "*.min.js", "--include", "b.*"], ""));
+    assert_eq!(vec!["src/a.rs", "web/c.js"], select(&["--iglob", "!*.min.js", "--exclude", "*/A.JS"], "/[sw]*/*"));
+    assert_eq!(vec!["web/A.JS", "web/c.js"], select(&["--exclude-dir", "src", "--exclude-dir", "target"], "/**/*"));
+    let args = vec![
+        "./minigrep".to_string(),
+        "--include".to_string(),
+        "[a-".to_string(),
+        "needle".to_string(),
+        dir];
+    assert_eq!(ConfigError::IllegalOptionValue("[a-".to_string()), GrepConfig::new(&args).err().unwrap());
+}
+
+#[test]
+fn test_grep_file_types() {
+    let dir = std::env::temp_dir().join(format!("minigrep-file-types-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in [("a.rs", "needle\n"), ("b.js", "needle\n"), ("c.proto", "needle\n"),
+                             ("run", "#!/usr/bin/env python3\nneedle\n"), ("setup", "#!/bin/bash\nneedle\n"),
+                             ("notes", "needle\n")] {
+        std::fs::write(dir.join(file), contents).unwrap();
+    }
+    let dir = dir.to_str().unwrap().to_string();
+    let select = |options: &[&str]| {
+        let mut args = vec!["./minigrep".to_string(), "needle".to_string(), dir.clone()];
+        args.extend(options.iter().map(|option| option.to_string()));
+        let config = GrepConfig::new(&args).unwrap();
+        config.filenames.iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(vec!["a.rs"], select(&["-t", "rust"]));
+    assert_eq!(vec!["a.rs", "b.js", "run"], select(&["-trust", "--type=web", "--type", "py"]));
+    assert_eq!(vec!["a.rs", "c.proto", "notes", "run"], select(&["-T", "js", "-T", "sh"]));
+    assert_eq!(vec!["c.proto"], select(&["--type-add", "proto:*.proto", "-t", "proto"]));
+    // the globs come first
+    assert_eq!(vec!["a.rs", "notes"], select(&["-t", "rust", "--include", "notes", "--include", "*.rs"]));
+
+    let args = vec!["./minigrep".to_string(), "--type-add=proto:*.proto".to_string(), "--type-list".to_string()];
+    let lines = GrepConfig::new(&args).unwrap().type_list().unwrap();
+    assert!(lines.contains(&"proto: *.proto".to_string()));
+    assert!(lines.contains(&"rust: *.rs".to_string()));
+    for (option, value) in [("-t", "klingon"), ("--type-add", "proto"), ("--type-add", ":*.proto")] {
+        let args = vec!["./minigrep".to_string(), option.to_string(), value.to_string(), "needle".to_string(), dir.clone()];
+        assert_eq!(ConfigError::IllegalOptionValue(value.to_string()), GrepConfig::new(&args).err().unwrap());
+    }
+}
+
+#[test]
+fn test_grep_metadata_filters() {
+    use std::time::{Duration, SystemTime};
+    let dir = std::env::temp_dir().join(format!("minigrep-metadata-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("big.log"), "needle big\n".repeat(1000)).unwrap();
+    std::fs::write(dir.join("new.log"), "needle new\n").unwrap();
+    std::fs::write(dir.join("old.log"), "needle old\n").unwrap();
+    let two_days_ago = SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60);
+    std::fs::File::options().write(true).open(dir.join("old.log")).unwrap()
+        .set_modified(two_days_ago).unwrap();
+    let dir = dir.to_str().unwrap().to_string();
+    let search = |options: &[&str]| {
+        let mut args = vec!["./minigrep".to_string(), "needle".to_string(), dir.clone()];
+        args.extend(options.iter().map(|option| option.to_string()));
+        let config = GrepConfig::new(&args).unwrap();
+        let mut names: Vec<String> = grep::GrepResult::new(&config).unwrap().to_lines(false).iter()
+            // the file each line came from is the last word of its text
+            .map(|line| format!("{}.log", line.rsplit(' ').next().unwrap()))
+            .collect();
+        names.dedup();
+        names
+    };
+    assert_eq!(vec!["new.log", "old.log"], search(&["--max-filesize", "1K"]));
+    assert_eq!(vec!["big.log", "new.log"], search(&["--newer-than=1d"]));
+    assert_eq!(vec!["old.log"], search(&["--older-than", "1h30m"]));
+    assert_eq!(vec!["big.log", "new.log", "old.log"], search(&["--newer-than", "2020-02-29T12:00"]));
+    assert_eq!(Vec::<String>::new(), search(&["--older-than", "1970-01-02"]));
+    let old = format!("{}/old.log", dir);
+    assert_eq!(vec!["big.log", "new.log"], search(&["--newer", &old]));
+    for (option, value) in [("--max-filesize", "10X"), ("--newer-than", "2h30"), ("--older-than", "2024-02-30x"),
+                            ("--owner", "no-such-user-here")] {
+        let args = vec!["./minigrep".to_string(), option.to_string(), value.to_string(), "needle".to_string(), dir.clone()];
+        assert_eq!(ConfigError::IllegalOptionValue(value.to_string()), GrepConfig::new(&args).err().unwrap());
+    }
+}
+
+#[test]
+fn test_grep_is_match() {
+    let config = GrepConfig {
+        queries: vec!["this".to_string()],
+        filenames: vec![PathBuf::from("demo_file.txt"), PathBuf::from("no_such_file.txt")],
+        options: vec![GrepOption::Quiet].into_iter().collect(),
+        ..Default::default()
+    };
+    // the file that does not exist is never reached
+    assert!(grep::is_match(&config, &mut |_err| ()).unwrap());
+    let config = GrepConfig {
+        queries: vec!["no such line".to_string()],
+        filenames: vec![PathBuf::from("demo_file.txt")],
+        ..Default::default()
+    };
+    assert!(!grep::is_match(&config, &mut |_err| ()).unwrap());
+    assert!(!grep::GrepResult::new(&config).unwrap().has_match());
+}
+
+#[test]
+fn test_grep_file_errors() {
+    let config = GrepConfig {
+        queries: vec!["this".to_string()],
+        filenames: vec![
+            PathBuf::from("no_such_file.txt"),
+            PathBuf::from("demo_file.txt"),
+            PathBuf::from("src"),
+        ],
+        ..Default::default()
+    };
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    // the files that could be searched still are
+    assert_eq!(3, grep_result.to_lines(false).len());
+    let errors: Vec<(String, std::io::ErrorKind)> = grep_result
+        .errors()
+        .iter()
+        .map(|err| match err {
+            GrepError::File(path, err) => (path.to_str().unwrap().to_string(), err.kind()),
+            _ => panic!("{:?}", err),
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            ("no_such_file.txt".to_string(), std::io::ErrorKind::NotFound),
+            ("src".to_string(), std::io::ErrorKind::IsADirectory)
+        ],
+        errors
+    );
+}
+
+#[test]
+fn test_grep_error_display() {
+    use std::error::Error;
+    let config = GrepConfig {
+        queries: vec!["(".to_string()],
+        filenames: vec![PathBuf::from("demo_file.txt")],
+        ..Default::default()
+    };
+    let err = grep::GrepResult::new(&config).err().unwrap();
+    assert!(err.to_string().starts_with("Query error: "));
+    assert!(err.source().unwrap().is::<regex::Error>());
+
+    let err = ConfigError::Defaults(
+        "MINIGREP_OPTS".to_string(),
+        Box::new(ConfigError::UnknownOption("--bad".to_string())),
+    );
+    assert_eq!("In MINIGREP_OPTS: Unknown option: --bad", err.to_string());
+    assert_eq!(
+        "Unknown option: --bad",
+        err.source().unwrap().to_string()
+    );
+}
+
+#[test]
+fn test_grep_builder() {
+    let config = GrepConfig::builder()
+        .query("this")
+        .path("demo_file.txt")
+        .option(GrepOption::LineNumber)
+        .context(1)
+        .build()
+        .unwrap();
+    let args: Vec<String> = ["minigrep", "-n", "-C1", "this", "demo_file.txt"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    assert_eq!(
+        grep::GrepResult::new(&GrepConfig::new(&args).unwrap()).unwrap().to_lines(true),
+        grep::GrepResult::new(&config).unwrap().to_lines(true)
+    );
+    // directories and globs are looked up by `build`
+    let config = GrepConfig::builder().query("fn").path("src/*.rs").build().unwrap();
+    assert!(config.filenames.contains(&PathBuf::from("src/lib.rs")));
+
+    let errors = [
+        (
+            GrepConfig::builder().path("demo_file.txt").build(),
+            ConfigError::MissingArg("query".to_string()),
+        ),
+        (
+            GrepConfig::builder().query("this").build(),
+            ConfigError::MissingArg("filename".to_string()),
+        ),
+        (
+            GrepConfig::builder().query("this").path("demo_file.txt").field("2").build(),
+            ConfigError::IllegalOptionValue("--field".to_string()),
+        ),
+        (
+            GrepConfig::builder()
+                .query("this")
+                .path("demo_file.txt")
+                .table_format(TableFormat::Csv)
+                .json_path(".msg")
+                .build(),
+            ConfigError::Conflict("--json-path".to_string(), "--csv".to_string()),
+        ),
+    ];
+    for (result, err) in errors {
+        assert_eq!(Some(err), result.err());
+    }
+}
+
+#[test]
+fn test_grep_ignored_option_combinations() {
+    // each combination would have an option ignored, so the option is rejected instead
+    let cases: Vec<(Vec<&str>, ConfigError)> = vec![
+        (vec!["--header"], ConfigError::IllegalOptionValue("--header".to_string())),
+        (vec!["--field", "2"], ConfigError::IllegalOptionValue("--field".to_string())),
+        (vec!["--preserve-case"], ConfigError::IllegalOptionValue("--preserve-case".to_string())),
+    ];
+    for (options, err) in cases {
+        let mut args = vec!["./minigrep".to_string()];
+        args.extend(options.iter().map(|arg| arg.to_string()));
+        args.extend(["this".to_string(), "demo_file.txt".to_string()]);
+        assert_eq!(Some(err), GrepConfig::new(&args).err(), "{:?}", options);
+    }
+    // a conflict names both options
+    let conflicts: Vec<(Vec<&str>, (&str, &str))> = vec![
+        (vec!["--csv", "--json-path", ".msg"], ("--json-path", "--csv")),
+        (vec!["--csv", "--state-file", "state"], ("--state-file", "--csv")),
+        (vec!["--json-path", ".msg", "--state-file", "state"], ("--state-file", "--json-path")),
+        (vec!["-z", "--state-file", "state"], ("--state-file", "-z")),
+        (vec!["--json", "-c"], ("--json", "-c")),
+        (vec!["--json", "-o"], ("--json", "-o")),
+        (vec!["--json", "-q"], ("--json", "-q")),
+        (vec!["--follow", "--json"], ("--follow", "--json")),
+        (vec!["--follow", "--csv"], ("--follow", "--csv")),
+        (vec!["--follow", "-c"], ("--follow", "-c")),
+        (vec!["--follow", "-q"], ("--follow", "-q")),
+        (vec!["--follow", "--index"], ("--follow", "--index")),
+        (vec!["--follow", "--state-file", "state"], ("--follow", "--state-file")),
+        (vec!["-q", "-c"], ("-q", "-c")),
+        (vec!["-q", "-o"], ("-q", "-o")),
+        (vec!["-q", "-n"], ("-q", "-n")),
+        (vec!["-q", "-r", "X"], ("-q", "-r")),
+        (vec!["-q", "-C", "1"], ("-q", "-C")),
+        (vec!["-c", "-o"], ("-c", "-o")),
+        (vec!["-c", "-n"], ("-c", "-n")),
+        (vec!["-c", "-r", "X"], ("-c", "-r")),
+        (vec!["-c", "-A", "1"], ("-c", "-A")),
+        (vec!["-v", "-o"], ("-o", "-v")),
+        (vec!["-o", "-B", "1"], ("-o", "-B")),
+    ];
+    for (options, (option, ignored)) in conflicts {
+        let mut args = vec!["./minigrep".to_string()];
+        args.extend(options.iter().map(|arg| arg.to_string()));
+        args.extend(["this".to_string(), "demo_file.txt".to_string()]);
+        assert_eq!(Some(ConfigError::Conflict(option.to_string(), ignored.to_string())),
+            GrepConfig::new(&args).err(), "{:?}", options);
+    }
+    // the same checks apply to the builder
+    let result = GrepConfig::builder()
+        .query("this")
+        .path("demo_file.txt")
+        .option(GrepOption::Follow)
+        .option(GrepOption::Quiet)
+        .build();
+    let err = result.err().unwrap();
+    assert_eq!(ConfigError::Conflict("--follow".to_string(), "-q".to_string()), err);
+    assert_eq!("--follow cannot be used with -q", err.to_string());
+}
+
+#[test]
+fn test_grep_result_iterators() {
+    let config = GrepConfig::builder()
+        .query("no such line")
+        .query("t[a-z]+s")
+        .path("demo_file.txt")
+        .after_context(1)
+        .build()
+        .unwrap();
+    let grep_result = grep::GrepResult::new(&config).unwrap();
+    assert_eq!(1, grep_result.files().count());
+    let file = grep_result.files().next().unwrap();
+    assert_eq!(std::path::Path::new("demo_file.txt"), file.path());
+    assert!(file.members().is_empty());
+
+    let (file, line) = grep_result.lines().next().unwrap();
+    assert_eq!("demo_file.txt", file.display_path());
+    assert_eq!(LineKind::Match, line.kind());
+    let submatch = &line.submatches()[0];
+    assert_eq!(1, submatch.pattern_index());
+    assert!(line.text()[submatch.span()].starts_with('t'));
+    assert!(submatch.replacement().is_none());
+
+    // the byte offsets add up to the lengths of the lines before
+    let contents = std::fs::read_to_string("demo_file.txt").unwrap();
+    for (_, line) in grep_result.lines() {
+        let start = line.byte_offset() as usize;
+        assert!(contents[start..].starts_with(line.text()));
+        let line_number = contents[..start].matches('\n').count() + 1;
+        assert_eq!(line_number as i32, line.line_number());
+    }
+    assert_eq!(
+        grep_result.to_lines(false).len(),
+        grep_result.lines().count()
+    );
+    assert!(grep_result
+        .lines()
+        .any(|(_, line)| line.kind() == LineKind::Context));
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::Path;
+
+use minigrep::grep;
+use minigrep::grep_config::GrepConfig;
+use minigrep::index;
+
+mod common;
+use common::ScratchDir;
+
+fn scratch_tree(name: &str) -> ScratchDir {
+    let dir = ScratchDir::new(&format!("index-{}", name));
+    dir.file("a.txt", "alpha beta\ngamma\n");
+    dir.file("src/b.txt", "delta\nepsilon\n");
+    dir.file("src/nested/c.txt", "Gamma Delta\n");
+    dir.file("binary.bin", [0xff, 0xfe, b'g', b'a', b'm']);
+    dir
+}
+
+/// The output of searching `dir` through its index, with `dir` taken off the paths.
+fn search(dir: &Path, options: &[&str]) -> String {
+    let mut args = vec!["./minigrep".to_string(), "--index".to_string()];
+    args.extend(options.iter().map(|option| option.to_string()));
+    args.push(dir.to_str().unwrap().to_string());
+    let config = GrepConfig::new(&args).unwrap();
+    let out = grep::GrepResult::new(&config).unwrap().to_string(&config);
+    out.replace(&format!("{}/", dir.display()), "")
+}
+
+/// Rewrites a file with contents of the same size and the same mtime, which the index
+/// cannot tell apart from the original.
+fn rewrite_unnoticed(path: &Path, contents: &str) {
+    let mtime = fs::metadata(path).unwrap().modified().unwrap();
+    fs::write(path, contents).unwrap();
+    fs::File::options().write(true).open(path).unwrap().set_modified(mtime).unwrap();
+}
+
+#[test]
+fn test_index_search() {
+    let dir = scratch_tree("search");
+    assert_eq!(4, index::build(&dir).unwrap());
+    assert!(dir.join(".minigrep-index").is_file());
+    assert_eq!("a.txt:gamma", search(&dir, &["gam+a"]));
+    assert_eq!("a.txt:gamma\nsrc/nested/c.txt:Gamma Delta", search(&dir, &["-i", "gamma"]));
+    assert_eq!("src/b.txt:delta\nsrc/nested/c.txt:Gamma Delta", search(&dir, &["-e", "delta", "-e", "Delta"]));
+    // no required trigram, so every file is searched
+    assert_eq!("a.txt:alpha beta\nsrc/b.txt:epsilon", search(&dir, &["beta|psi"]));
+}
+
+#[test]
+fn test_index_narrows_candidates() {
+    let dir = scratch_tree("narrow");
+    index::build(&dir).unwrap();
+    rewrite_unnoticed(&dir.join("src/b.txt"), "gamma\nepsilon\n");
+    assert_eq!("a.txt:gamma", search(&dir, &["gamma"]));
+    // without the index the rewritten file is found too
+    let args: Vec<String> = ["./minigrep", "gamma", dir.join("src/b.txt").to_str().unwrap()]
+        .iter().map(|arg| arg.to_string()).collect();
+    let config = GrepConfig::new(&args).unwrap();
+    assert_eq!("gamma", grep::GrepResult::new(&config).unwrap().to_string(&config));
+}
+
+#[test]
+fn test_index_stale_and_new_files() {
+    let dir = scratch_tree("stale");
+    index::build(&dir).unwrap();
+    fs::write(dir.join("src/b.txt"), "delta\nepsilon gamma\n").unwrap();
+    fs::write(dir.join("src/new.txt"), "gamma ray\n").unwrap();
+    fs::remove_file(dir.join("a.txt")).unwrap();
+    assert_eq!("src/b.txt:epsilon gamma\nsrc/new.txt:gamma ray", search(&dir, &["gamma"]));
+}
+
+#[test]
+fn test_index_missing() {
+    let dir = scratch_tree("missing");
+    let args: Vec<String> = ["./minigrep", "--index", "gamma", dir.to_str().unwrap()]
+        .iter().map(|arg| arg.to_string()).collect();
+    let config = GrepConfig::new(&args).unwrap();
+    assert!(matches!(grep::GrepResult::new(&config), Err(grep::GrepError::Index(_))));
+}
+
+/// Whether the index knows `path` with its current contents: it is rewritten with a
+/// marker of the same size and mtime, which only a search that has to open the file finds.
+fn is_indexed(dir: &Path, path: &str) -> bool {
+    let size = fs::metadata(dir.join(path)).unwrap().len() as usize;
+    rewrite_unnoticed(&dir.join(path), &"q".repeat(size));
+    !search(dir, &["qqq"]).contains("qqq")
+}
+
+#[cfg(target_os = "linux")]
+fn watch_while(dir: &Path, steps: impl FnOnce()) {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    let is_done = Arc::new(AtomicBool::new(false));
+    let watcher = {
+        let dir = dir.to_path_buf();
+        let is_done = is_done.clone();
+        thread::spawn(move || index::watch(&dir, &|| is_done.load(Ordering::SeqCst)))
+    };
+    thread::sleep(Duration::from_millis(300));
+    steps();
+    thread::sleep(Duration::from_millis(500));
+    is_done.store(true, Ordering::SeqCst);
+    watcher.join().unwrap().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_index_watch() {
+    let dir = scratch_tree("watch");
+    index::build(&dir).unwrap();
+    watch_while(&dir, || {
+        fs::write(dir.join("a.txt"), "alpha\nzeta\n").unwrap();
+        fs::write(dir.join("src/new.txt"), "zeta new\n").unwrap();
+        fs::rename(dir.join("src/nested"), dir.join("moved")).unwrap();
+        fs::write(dir.join("moved/d.txt"), "zeta moved\n").unwrap();
+        fs::remove_file(dir.join("src/b.txt")).unwrap();
+    });
+    assert!(dir.join(".minigrep-index.journal").is_file());
+    assert_eq!("a.txt:zeta\nmoved/d.txt:zeta moved\nsrc/new.txt:zeta new", search(&dir, &["zeta"]));
+    assert!(is_indexed(&dir, "a.txt"));
+    assert!(is_indexed(&dir, "moved/c.txt"));
+    assert!(is_indexed(&dir, "moved/d.txt"));
+    assert!(is_indexed(&dir, "src/new.txt"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_index_watch_catches_up_and_compacts() {
+    let dir = scratch_tree("catch-up");
+    index::build(&dir).unwrap();
+    // changed while nothing watched
+    fs::write(dir.join("src/b.txt"), "theta\n").unwrap();
+    fs::create_dir(dir.join("lib")).unwrap();
+    fs::write(dir.join("lib/e.txt"), "theta too\n").unwrap();
+    watch_while(&dir, || ());
+    assert!(!dir.join(".minigrep-index.journal").exists());
+    assert_eq!("lib/e.txt:theta too\nsrc/b.txt:theta", search(&dir, &["theta"]));
+    assert!(is_indexed(&dir, "src/b.txt"));
+    assert!(is_indexed(&dir, "lib/e.txt"));
+}
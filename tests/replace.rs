@@ -0,0 +1,175 @@
+use std::fs;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use minigrep::grep::{self, GrepError};
+use minigrep::grep_config::{ConfigError, GrepConfig};
+use minigrep::replace::{self, ReplaceConfig};
+
+mod common;
+use common::ScratchDir;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn test_replace_dry_run() {
+    let dir = ScratchDir::new("replace-dry-run");
+    let path = dir.file("dry_run.txt", "foo one\nbar\nbaz\nqux\nx\ny\nz\nw\nfoo two\nend");
+    let config = ReplaceConfig::new(&args(&[
+        "./minigrep", "replace", "--dry-run", "-r", "F($1)", "(fo+)", path.to_str().unwrap()])).unwrap();
+    let mut out = Vec::new();
+    replace::replace_in_files(&config, &mut &b""[..], &mut out, &mut |_| ()).unwrap();
+    let expected = format!("--- a/{0}
++++ b/{0}
+@@ -1,4 +1,4 @@
+-foo one
++F(foo) one
+ bar
+ baz
+ qux
+@@ -6,5 +6,5 @@
+ y
+ z
+ w
+-foo two
++F(foo) two
+ end
+\\ No newline at end of file
+", path.to_str().unwrap());
+    assert_eq!(expected, String::from_utf8(out).unwrap());
+    // nothing is written on a dry run
+    assert_eq!("foo one\nbar\nbaz\nqux\nx\ny\nz\nw\nfoo two\nend", fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_replace_in_place() {
+    let dir = ScratchDir::new("replace-in-place");
+    let path = dir.file("in_place.txt", "let foo = 1;\r\nbar(foo);\r\n");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    let config = ReplaceConfig::new(&args(&[
+        "./minigrep", "replace", "--backup-suffix", ".orig", "-w", "-r", "baz", "foo", path.to_str().unwrap()])).unwrap();
+    replace::replace_in_files(&config, &mut &b""[..], &mut Vec::new(), &mut |_| ()).unwrap();
+    assert_eq!("let baz = 1;\r\nbar(baz);\r\n", fs::read_to_string(&path).unwrap());
+    assert_eq!(0o640, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+    let backup = PathBuf::from(format!("{}.orig", path.to_str().unwrap()));
+    assert_eq!("let foo = 1;\r\nbar(foo);\r\n", fs::read_to_string(backup).unwrap());
+}
+
+#[test]
+fn test_replace_requires_template() {
+    assert!(ReplaceConfig::new(&args(&["./minigrep", "replace", "foo", "demo_file.txt"])).is_err());
+}
+
+#[test]
+fn test_replace_rejects_selection_options() {
+    // the rewrite replaces every match of every line, so these would be ignored
+    for option in [vec!["-v"], vec!["-o"], vec!["--json"], vec!["--csv"], vec!["--csv", "--field", "2"],
+        vec!["--tsv"], vec!["--json-path", ".msg"], vec!["-z"]] {
+        let mut arguments = vec!["./minigrep", "replace", "-r", "X"];
+        arguments.extend(&option);
+        arguments.extend(["foo", "demo_file.txt"]);
+        assert_eq!(
+            Some(ConfigError::Conflict("replace".to_string(), option[0].to_string())),
+            ReplaceConfig::new(&args(&arguments)).err());
+    }
+}
+
+#[test]
+fn test_replace_interactive() {
+    let dir = ScratchDir::new("replace-interactive");
+    let path = dir.file("interactive.txt", "foo 1\nkeep\nfoo 2\nfoo 3\n");
+    let config = ReplaceConfig::new(&args(&[
+        "./minigrep", "replace", "--interactive", "-C", "1", "-r", "bar", "foo", path.to_str().unwrap()])).unwrap();
+    // an unknown answer asks again; `q` keeps the changes accepted so far
+    let mut input = &b"y\nmaybe\nn\nq\n"[..];
+    let mut out = Vec::new();
+    replace::replace_in_files(&config, &mut input, &mut out, &mut |_| ()).unwrap();
+    assert_eq!("bar 1\nkeep\nfoo 2\nfoo 3\n", fs::read_to_string(&path).unwrap());
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with(&format!("{}:1\n-foo 1\n+bar 1\n keep\nReplace? [y,n,a,q] ", path.to_str().unwrap())));
+    assert!(out.contains(&format!("{}:3\n keep\n-foo 2\n+bar 2\n foo 3\nReplace? [y,n,a,q] Replace? [y,n,a,q] ", path.to_str().unwrap())));
+}
+
+#[test]
+fn test_replace_interactive_per_match() {
+    let dir = ScratchDir::new("replace-interactive-per-match");
+    let path = dir.file("interactive_per_match.txt", "foo foo foo\n");
+    let config = ReplaceConfig::new(&args(&[
+        "./minigrep", "replace", "--interactive", "-r", "bar", "foo", path.to_str().unwrap()])).unwrap();
+    let mut input = &b"n\ny\nn\n"[..];
+    let mut out = Vec::new();
+    replace::replace_in_files(&config, &mut input, &mut out, &mut |_| ()).unwrap();
+    assert_eq!("foo bar foo\n", fs::read_to_string(&path).unwrap());
+    // each prompt shows the line with the matches accepted so far
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("-foo foo foo\n+bar foo foo\n"));
+    assert!(out.contains("-foo foo foo\n+foo bar foo\n"));
+    assert!(out.contains("-foo bar foo\n+foo bar bar\n"));
+}
+
+#[test]
+fn test_replace_interactive_piped_answers() {
+    let dir = ScratchDir::new("replace-interactive-piped-answers");
+    let path = dir.file("interactive_piped.txt", "foo 1\nfoo 2\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .args(["replace", "--no-config", "--interactive", "-r", "bar", "foo", path.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"n\ny\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("foo 1\nbar 2\n", fs::read_to_string(&path).unwrap());
+    assert_eq!(2, String::from_utf8(output.stdout).unwrap().matches("Replace? [y,n,a,q] ").count());
+}
+
+#[test]
+fn test_replace_interactive_all() {
+    let dir = ScratchDir::new("replace-interactive-all");
+    let path = dir.file("interactive_all.txt", "foo 1\nfoo 2\nfoo 3\n");
+    let config = ReplaceConfig::new(&args(&[
+        "./minigrep", "replace", "--interactive", "-r", "bar", "foo", path.to_str().unwrap()])).unwrap();
+    let mut input = &b"n\na\n"[..];
+    replace::replace_in_files(&config, &mut input, &mut Vec::new(), &mut |_| ()).unwrap();
+    assert_eq!("foo 1\nbar 2\nbar 3\n", fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+fn test_replace_preserve_case() {
+    let dir = ScratchDir::new("replace-preserve-case");
+    let path = dir.file("preserve_case.txt", "let foo_bar = FooBar::new();\nconst FOO_BAR: u8 = fooBar(foo-bar);\n");
+    let config = ReplaceConfig::new(&args(&[
+        "./minigrep", "replace", "--preserve-case", "-r", "baz_qux", "foo_bar", path.to_str().unwrap()])).unwrap();
+    replace::replace_in_files(&config, &mut &b""[..], &mut Vec::new(), &mut |_| ()).unwrap();
+    assert_eq!("let baz_qux = BazQux::new();\nconst BAZ_QUX: u8 = bazQux(baz-qux);\n", fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+fn test_replace_preserve_case_output() {
+    let dir = ScratchDir::new("replace-preserve-case-output");
+    let path = dir.file("preserve_case_output.txt", "Foo foo FOO\n");
+    let config = GrepConfig::new(&args(&[
+        "./minigrep", "--preserve-case", "-r", "bar", "foo", path.to_str().unwrap()])).unwrap();
+    let matches = grep::GrepResult::new(&config).unwrap().to_lines(false);
+    assert_eq!(vec!["Bar bar BAR".to_string()], matches);
+}
+
+#[test]
+fn test_replace_keeps_going_after_file_error() {
+    let dir = ScratchDir::new("replace-keeps-going-after-file-error");
+    let bad = dir.file("not_utf8.txt", b"foo \xff\n");
+    let good = dir.file("after_error.txt", "foo\n");
+    let config = ReplaceConfig::new(&args(&[
+        "./minigrep", "replace", "-r", "bar", "foo", bad.to_str().unwrap(), good.to_str().unwrap()])).unwrap();
+    let mut errors = Vec::new();
+    replace::replace_in_files(&config, &mut &b""[..], &mut Vec::new(), &mut |err| errors.push(err)).unwrap();
+    assert!(matches!(errors[..], [GrepError::NotValidUTF8(ref path)] if *path == bad));
+    assert_eq!("bar\n", fs::read_to_string(&good).unwrap());
+}
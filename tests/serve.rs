@@ -0,0 +1,179 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use minigrep::serve;
+
+mod common;
+use common::ScratchDir;
+
+fn start_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || serve::serve(listener));
+    addr
+}
+
+fn send(addr: SocketAddr, method: &str, path: &str, body: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+    stream
+}
+
+/// Returns the status line and the body, with chunked encoding undone.
+fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (String, String) {
+    let mut reader = BufReader::new(send(addr, method, path, body));
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    let mut is_chunked = false;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header == "\r\n" {
+            break;
+        }
+        is_chunked |= header.eq_ignore_ascii_case("transfer-encoding: chunked\r\n");
+    }
+    let mut body = Vec::new();
+    if is_chunked {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).unwrap();
+            let size = usize::from_str_radix(size.trim_end(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).unwrap();
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else {
+        reader.read_to_end(&mut body).unwrap();
+    }
+    (status.trim_end().to_string(), String::from_utf8(body).unwrap())
+}
+
+fn message_types(body: &str) -> Vec<String> {
+    body.lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["type"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_serve_search() {
+    let addr = start_server();
+    let (status, body) = request(addr, "POST", "/search",
+        r#"{"query": "EMPTY", "options": ["-i", "-n"], "paths": ["demo_file.txt", "demo_file1.txt"]}"#);
+    assert_eq!("HTTP/1.1 200 OK", status);
+    // one search over all the paths, with a single summary
+    assert_eq!(vec!["begin", "match", "end", "begin", "match", "end", "summary"],
+        message_types(&body));
+    assert!(body.contains(r#""line_number":5,"lines":{"text":"Two lines above this line is empty.\n"}"#));
+}
+
+#[test]
+fn test_serve_errors() {
+    let addr = start_server();
+    let (status, body) = request(addr, "POST", "/search", r#"{"options": []}"#);
+    assert_eq!("HTTP/1.1 400 Bad Request", status);
+    assert_eq!(r#"{"error":"`query` must be a string"}"#, body);
+    let (status, _) = request(addr, "POST", "/search", r#"{"query": "x", "options": ["--bogus"], "paths": ["demo_file.txt"]}"#);
+    assert_eq!("HTTP/1.1 400 Bad Request", status);
+    // only options that change the search itself are accepted
+    for options in [r#"["--state-file", "state"]"#, r#"["--follow"]"#, r#"["--index"]"#, r#"["--no-config"]"#,
+        r#"["-r", "x"]"#, r#"["--type-add", "x:*.x"]"#, r#"["-ir", "x"]"#, r#"["--debug"]"#, r#"["-c"]"#] {
+        let (status, body) = request(addr, "POST", "/search",
+            &format!(r#"{{"query": "x", "options": {}, "paths": ["demo_file.txt"]}}"#, options));
+        assert_eq!("HTTP/1.1 400 Bad Request", status);
+        assert!(body.contains("option not allowed"), "{}", body);
+    }
+    // a path is never taken for an option
+    let (status, body) = request(addr, "POST", "/search", r#"{"query": "empty", "paths": ["--state-file", "demo_file.txt"]}"#);
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(vec!["error", "begin", "match", "end", "summary"], message_types(&body));
+    let (status, _) = request(addr, "GET", "/search", "");
+    assert_eq!("HTTP/1.1 405 Method Not Allowed", status);
+    let (status, _) = request(addr, "POST", "/other", "");
+    assert_eq!("HTTP/1.1 404 Not Found", status);
+    // search errors are reported in the stream
+    let (status, body) = request(addr, "POST", "/search", r#"{"query": "(", "paths": ["demo_file.txt"]}"#);
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(vec!["error"], message_types(&body));
+}
+
+#[test]
+fn test_serve_client_disconnect() {
+    let addr = start_server();
+    let dir = ScratchDir::new("serve-disconnect");
+    let big = dir.file("big.txt", "match me\n".repeat(200_000));
+    let body = format!(r#"{{"query": "match", "paths": ["{}"]}}"#, big.to_str().unwrap());
+    drop(send(addr, "POST", "/search", &body));
+    // the server keeps answering after a client went away mid-search
+    let (status, body) = request(addr, "POST", "/search", r#"{"query": "empty", "paths": ["demo_file.txt"]}"#);
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(vec!["begin", "match", "end", "summary"], message_types(&body));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_serve_disconnect_stops_search_in_file() {
+    let addr = start_server();
+    let dir = ScratchDir::new("serve-stop-in-file");
+    let fifo = dir.join("fifo");
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+    let body = format!(r#"{{"query": "match", "paths": ["{}"]}}"#, fifo.to_str().unwrap());
+    let stream = send(addr, "POST", "/search", &body);
+    let mut writer = OpenOptions::new().write(true).open(&fifo).unwrap();
+    writer.write_all(b"match me\n").unwrap();
+    // no results are written until the file ends, but the search still stops
+    stream.shutdown(Shutdown::Both).unwrap();
+    drop(stream);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let is_stopped = loop {
+        match writer.write_all(b"match me\n") {
+            Err(err) => break err.kind() == ErrorKind::BrokenPipe,
+            Ok(()) if Instant::now() > deadline => break false,
+            Ok(()) => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    assert!(is_stopped);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_serve_disconnect_stops_search() {
+    let addr = start_server();
+    let dir = ScratchDir::new("serve-stop");
+    let first = dir.join("first");
+    let second = dir.join("second");
+    for fifo in [&first, &second] {
+        assert!(Command::new("mkfifo").arg(fifo).status().unwrap().success());
+    }
+    let body = format!(r#"{{"query": "match", "paths": ["{}", "{}"]}}"#,
+        first.to_str().unwrap(), second.to_str().unwrap());
+    // the search waits on the first pipe while the client goes away
+    drop(send(addr, "POST", "/search", &body));
+    let mut writer = OpenOptions::new().write(true).open(&first).unwrap();
+    // the search may stop before reading the pipe to its end
+    let _ = writer.write_all("match me\n".repeat(10_000).as_bytes());
+    drop(writer);
+    // the search of the first pipe stops, so the second is never opened
+    let is_opened = Arc::new(AtomicBool::new(false));
+    {
+        let is_opened = is_opened.clone();
+        let second = second.clone();
+        thread::spawn(move || {
+            let _writer = OpenOptions::new().write(true).open(&second).unwrap();
+            is_opened.store(true, Ordering::SeqCst);
+        });
+    }
+    thread::sleep(Duration::from_secs(1));
+    assert!(!is_opened.load(Ordering::SeqCst));
+    // let the waiting writer go
+    drop(File::open(&second).unwrap());
+}
@@ -0,0 +1,92 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use minigrep::grep;
+use minigrep::grep_config::GrepConfig;
+
+mod common;
+use common::ScratchDir;
+
+fn append(path: &Path, text: &str) {
+    let mut f = OpenOptions::new().append(true).open(path).unwrap();
+    f.write_all(text.as_bytes()).unwrap();
+}
+
+fn run(log: &Path, state: &Path) -> String {
+    let args: Vec<String> = ["./minigrep", "-n", "--state-file", state.to_str().unwrap(), "error", log.to_str().unwrap()]
+        .iter().map(|arg| arg.to_string()).collect();
+    let config = GrepConfig::new(&args).unwrap();
+    grep::GrepResult::new(&config).unwrap().to_string(&config)
+}
+
+#[test]
+fn test_state_file_resumes() {
+    let dir = ScratchDir::new("state-resume");
+    let log = dir.join("app.log");
+    let state = dir.join("state");
+    fs::write(&log, "error one\ninfo\n").unwrap();
+    assert_eq!("1:error one", run(&log, &state));
+    assert_eq!("", run(&log, &state));
+    // an unfinished last line waits for its line ending
+    append(&log, "info\nerror two\nerror thr");
+    assert_eq!("4:error two", run(&log, &state));
+    append(&log, "ee\n");
+    assert_eq!("5:error three", run(&log, &state));
+}
+
+#[test]
+fn test_state_file_starts_over() {
+    let dir = ScratchDir::new("state-reset");
+    let log = dir.join("app.log");
+    let state = dir.join("state");
+    fs::write(&log, "error one\nerror two\n").unwrap();
+    assert_eq!("1:error one\n2:error two", run(&log, &state));
+    // truncated
+    fs::write(&log, "error new\n").unwrap();
+    assert_eq!("1:error new", run(&log, &state));
+    // replaced by a new file that is already longer than the old offset
+    fs::rename(&log, dir.join("app.log.1")).unwrap();
+    fs::write(&log, "info\ninfo\nerror rotated\n").unwrap();
+    assert_eq!("3:error rotated", run(&log, &state));
+}
+
+#[test]
+fn test_state_file_truncated_and_regrown() {
+    let dir = ScratchDir::new("state-regrow");
+    let log = dir.join("app.log");
+    let state = dir.join("state");
+    fs::write(&log, "error one\nerror two\n").unwrap();
+    assert_eq!("1:error one\n2:error two", run(&log, &state));
+    // truncated in place, keeping its inode, and grown past the old offset before the next run
+    fs::write(&log, "info about to restart\nerror new\n").unwrap();
+    assert_eq!("2:error new", run(&log, &state));
+    append(&log, "error appended\n");
+    assert_eq!("3:error appended", run(&log, &state));
+}
+
+#[test]
+fn test_state_file_quiet() {
+    let dir = ScratchDir::new("state-quiet");
+    let first = dir.file("a.log", "error in a\n");
+    let second = dir.file("b.log", "error in b\n");
+    let state = dir.join("state");
+    let args: Vec<String> = ["./minigrep", "-q", "--state-file", state.to_str().unwrap(), "error",
+        first.to_str().unwrap(), second.to_str().unwrap()]
+        .iter().map(|arg| arg.to_string()).collect();
+    let config = GrepConfig::new(&args).unwrap();
+    // each run stops at its first selected line, and the files searched by then are saved
+    assert!(grep::is_match(&config, &mut |_| ()).unwrap());
+    assert!(grep::is_match(&config, &mut |_| ()).unwrap());
+    assert!(!grep::is_match(&config, &mut |_| ()).unwrap());
+    append(&first, "error again\n");
+    assert!(grep::is_match(&config, &mut |_| ()).unwrap());
+    assert!(!grep::is_match(&config, &mut |_| ()).unwrap());
+}
+
+#[test]
+fn test_state_file_rejects_table_mode() {
+    let args: Vec<String> = ["./minigrep", "--csv", "--state-file", "state", "x", "demo_table.csv"]
+        .iter().map(|arg| arg.to_string()).collect();
+    assert!(GrepConfig::new(&args).is_err());
+}